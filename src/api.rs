@@ -0,0 +1,88 @@
+use std::fmt;
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use serde::de::DeserializeOwned;
+use crate::loader::{Authorization, HostApp, Lockfile, LoaderError, UserId};
+use crate::name_service::NameService;
+use crate::pre_game::{PreGame, PreGameId};
+use crate::r#match::{CurrentGameMatch, CurrentGamePlayer};
+use crate::match_details::MatchDetails;
+use crate::riot_client::RiotError;
+
+#[cfg(test)]
+pub mod fixtures;
+
+// Calls the Loader makes to set itself up, mostly against the local Riot Client
+#[async_trait]
+pub trait LocalApi: fmt::Debug + Send + Sync {
+    async fn entitlements(&self, lockfile: &Lockfile) -> Result<Authorization, LoaderError>;
+    async fn external_sessions(&self, lockfile: &Lockfile) -> Result<HostApp, LoaderError>;
+    async fn user_info(&self, access_token: &str) -> Result<UserId, LoaderError>;
+}
+
+// Remote PVP calls made while looking for and loading a match
+#[async_trait]
+pub trait PvpApi: Send + Sync {
+    // The player Val+ is running for
+    fn puuid(&self) -> &str;
+
+    async fn core_game_player(&self) -> Result<CurrentGamePlayer, RiotError>;
+    async fn core_game_match(&self, match_id: &str) -> Result<CurrentGameMatch, RiotError>;
+    async fn pre_game_player(&self) -> Result<PreGameId, RiotError>;
+    async fn pre_game_match(&self, match_id: &str) -> Result<PreGame, RiotError>;
+    async fn names(&self, puuids: &[String]) -> Result<Vec<NameService>, RiotError>;
+    async fn match_details(&self, match_id: &str) -> Result<MatchDetails, RiotError>;
+}
+
+#[derive(Debug, Default)]
+pub struct LocalClient {
+    http: Client,
+    auth_url: String,
+}
+
+impl LocalClient {
+    pub fn new(http: Client, auth_url: String) -> Self {
+        Self { http, auth_url }
+    }
+
+    // Sends a request and decodes the JSON body, keeping the endpoint name for error messages
+    async fn send_json<T: DeserializeOwned>(endpoint: &'static str, request: RequestBuilder) -> Result<T, LoaderError> {
+        let res = request.send().await.map_err(|source| LoaderError::Request { endpoint, source })?;
+
+        if !res.status().is_success() {
+            return Err(LoaderError::Status { endpoint, status: res.status() })
+        }
+
+        res.json::<T>().await.map_err(|source| LoaderError::Deserialize { endpoint, source })
+    }
+}
+
+#[async_trait]
+impl LocalApi for LocalClient {
+    async fn entitlements(&self, lockfile: &Lockfile) -> Result<Authorization, LoaderError> {
+        Self::send_json(
+            "entitlements",
+            self.http.get(format!("https://127.0.0.1:{}/entitlements/v1/token", lockfile.port)).basic_auth("riot", Some(&lockfile.password)),
+        ).await
+    }
+
+    async fn external_sessions(&self, lockfile: &Lockfile) -> Result<HostApp, LoaderError> {
+        Self::send_json(
+            "external-sessions",
+            self.http.get(format!("https://127.0.0.1:{}/product-session/v1/external-sessions", lockfile.port)).basic_auth("riot", Some(&lockfile.password)),
+        ).await
+    }
+
+    async fn user_info(&self, access_token: &str) -> Result<UserId, LoaderError> {
+        Self::send_json(
+            "userinfo",
+            self.http.get(format!("{}/userinfo", self.auth_url)).bearer_auth(access_token),
+        ).await
+    }
+}
+
+impl Default for Box<dyn LocalApi> {
+    fn default() -> Self {
+        Box::new(LocalClient::default())
+    }
+}
@@ -0,0 +1,115 @@
+// Recorded responses from the Riot Client and PVP endpoints, so tests run without a client or network
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use crate::api::{LocalApi, PvpApi};
+use crate::loader::{Authorization, HostApp, Lockfile, LoaderError, UserId};
+use crate::name_service::NameService;
+use crate::pre_game::{PreGame, PreGameId};
+use crate::r#match::{CurrentGameMatch, CurrentGamePlayer};
+use crate::match_details::MatchDetails;
+use crate::riot_client::RiotError;
+
+pub const PUUID: &str = "4c4a9d6e-1f2b-5c3d-9e8f-0a1b2c3d4e5f";
+pub const MATCH_ID: &str = "9d3b2f1e-8c7a-4b6d-a5e4-3f2c1b0a9e8d";
+
+pub const ENTITLEMENTS: &str = include_str!("../../tests/fixtures/entitlements.json");
+pub const EXTERNAL_SESSIONS: &str = include_str!("../../tests/fixtures/external_sessions.json");
+pub const USER_INFO: &str = include_str!("../../tests/fixtures/userinfo.json");
+pub const CORE_GAME_PLAYER: &str = include_str!("../../tests/fixtures/core_game_player.json");
+pub const CORE_GAME_MATCH: &str = include_str!("../../tests/fixtures/core_game_match.json");
+pub const PRE_GAME_PLAYER: &str = include_str!("../../tests/fixtures/pre_game_player.json");
+pub const PRE_GAME_MATCH: &str = include_str!("../../tests/fixtures/pre_game_match.json");
+pub const NAMES: &str = include_str!("../../tests/fixtures/names.json");
+pub const MATCH_DETAILS: &str = include_str!("../../tests/fixtures/match_details.json");
+
+fn parse<T: DeserializeOwned>(json: &str) -> T {
+    serde_json::from_str(json).expect("fixture should deserialize")
+}
+
+#[derive(Debug, Default)]
+pub struct FixtureLocalApi;
+
+#[async_trait]
+impl LocalApi for FixtureLocalApi {
+    async fn entitlements(&self, _lockfile: &Lockfile) -> Result<Authorization, LoaderError> {
+        Ok(parse(ENTITLEMENTS))
+    }
+
+    async fn external_sessions(&self, _lockfile: &Lockfile) -> Result<HostApp, LoaderError> {
+        Ok(parse(EXTERNAL_SESSIONS))
+    }
+
+    async fn user_info(&self, _access_token: &str) -> Result<UserId, LoaderError> {
+        Ok(parse(USER_INFO))
+    }
+}
+
+// Answers like the PVP servers would, endpoints for a phase the player isn't in return 404
+#[derive(Debug, Default)]
+pub struct FixturePvpApi {
+    pub in_pre_game: bool,
+    pub in_core_game: bool,
+    // Swaps the core game teams so the player is on Red
+    pub on_red_team: bool,
+    // Match details are only published once the match is over
+    pub match_finished: bool,
+    // Match whose details fail with a server error
+    pub failing_match: Option<&'static str>,
+}
+
+impl FixturePvpApi {
+    fn phase<T: DeserializeOwned>(active: bool, json: &str) -> Result<T, RiotError> {
+        if !active {
+            return Err(RiotError::Status(StatusCode::NOT_FOUND))
+        }
+
+        Ok(parse(json))
+    }
+}
+
+#[async_trait]
+impl PvpApi for FixturePvpApi {
+    fn puuid(&self) -> &str {
+        PUUID
+    }
+
+    async fn core_game_player(&self) -> Result<CurrentGamePlayer, RiotError> {
+        Self::phase(self.in_core_game, CORE_GAME_PLAYER)
+    }
+
+    async fn core_game_match(&self, _match_id: &str) -> Result<CurrentGameMatch, RiotError> {
+        if self.on_red_team {
+            let swapped = CORE_GAME_MATCH.replace("\"Blue\"", "\"Swap\"").replace("\"Red\"", "\"Blue\"").replace("\"Swap\"", "\"Red\"");
+            return Self::phase(self.in_core_game, &swapped)
+        }
+
+        Self::phase(self.in_core_game, CORE_GAME_MATCH)
+    }
+
+    async fn pre_game_player(&self) -> Result<PreGameId, RiotError> {
+        Self::phase(self.in_pre_game, PRE_GAME_PLAYER)
+    }
+
+    async fn pre_game_match(&self, _match_id: &str) -> Result<PreGame, RiotError> {
+        Self::phase(self.in_pre_game, PRE_GAME_MATCH)
+    }
+
+    async fn names(&self, puuids: &[String]) -> Result<Vec<NameService>, RiotError> {
+        let names: Vec<NameService> = parse(NAMES);
+
+        Ok(names.into_iter().filter(|name| puuids.contains(&name.uuid)).collect())
+    }
+
+    async fn match_details(&self, match_id: &str) -> Result<MatchDetails, RiotError> {
+        if self.failing_match == Some(match_id) {
+            return Err(RiotError::Status(StatusCode::INTERNAL_SERVER_ERROR))
+        }
+
+        if match_id != MATCH_ID {
+            return Err(RiotError::Status(StatusCode::NOT_FOUND))
+        }
+
+        Self::phase(self.match_finished, MATCH_DETAILS)
+    }
+}
@@ -31,26 +31,29 @@ impl Converter {
 
         if let Some(obj) = v.as_object() {
             for (uuid, player_data) in obj.iter() {
-                let mut total_matches: i64 = 0;
-                let mut last_played: i64 = 0;
                 let mut names: Vec<String> = Vec::new();
 
                 if let Some(data_array) = player_data.as_array() {
-                    total_matches = data_array.len() as i64;
-
                     for history in data_array {
                         total += 1;
-                        last_played = history.get("epoch").unwrap().as_f64().unwrap() as i64;
+                        let last_played = history.get("epoch").unwrap().as_f64().unwrap() as i64;
                         names.push(history.get("name").unwrap().to_string().trim_matches('\"').to_string());
 
+                        let match_id = history.get("match_id").unwrap().to_string();
+
+                        // VRY doesn't keep the mode, queue or server
+                        let _ = database::add_match(database::Match {
+                            match_id: match_id.clone(),
+                            map_id: if let Some(map_obj) = history.get("map").unwrap().as_object() { map_obj.get("name").unwrap().to_string().trim_matches('\"').to_string() } else { history.get("map").unwrap().to_string().trim_matches('\"').to_string() },
+                            start_time: last_played,
+                            ..Default::default()
+                        });
+
                         match database::add_new_match(
                             uuid.clone(),
-                            history.get("match_id").unwrap().to_string(),
-                             if let Some(map_obj) = history.get("map").unwrap().as_object() { map_obj.get("name").unwrap().to_string().trim_matches('\"').to_string() } else { history.get("map").unwrap().to_string().trim_matches('\"').to_string() },
-                            "".to_string(),
+                            match_id,
                             history.get("agent").unwrap().to_string().trim_matches('\"').to_string(),
                             false,
-                            last_played,
                         ) {
                             Ok(_) => success += 1,
                             Err(_) => fail += 1,
@@ -75,7 +78,8 @@ impl Converter {
                     }
                 }
 
-                match database::add_user_full(uuid.to_owned(), total_matches, last_played) {
+                // Counted from the imported matches, so duplicates aren't counted twice
+                match database::sync_user(uuid.to_owned()) {
                     Ok(_) => println!("Added new user"),
                     Err(_) => println!("Failed to add new full user"),
                 }
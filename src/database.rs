@@ -1,5 +1,8 @@
+use std::collections::HashMap;
+use std::fmt;
 use std::time::{SystemTime, UNIX_EPOCH};
-use turbosql::{execute, select, Turbosql, update};
+use turbosql::{execute, select, Turbosql};
+use turbosql::rusqlite::ffi;
 
 #[derive(Turbosql, Default, Debug, Clone)]
 pub struct UserDatabase {
@@ -7,18 +10,274 @@ pub struct UserDatabase {
     pub uuid: String,
     pub times_played: i64,
     pub last_played: i64,
+    // PlayerFlag::as_str, empty when not flagged
+    pub flag: String,
+    pub flag_reason: String,
 }
 
+impl UserDatabase {
+    pub fn flag(&self) -> Option<PlayerFlag> {
+        PlayerFlag::parse(&self.flag)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlayerFlag {
+    Avoid,
+    FriendOfFriend,
+    Watch,
+}
+
+impl PlayerFlag {
+    pub const ALL: [PlayerFlag; 3] = [PlayerFlag::Avoid, PlayerFlag::FriendOfFriend, PlayerFlag::Watch];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PlayerFlag::Avoid => "avoid",
+            PlayerFlag::FriendOfFriend => "friend_of_friend",
+            PlayerFlag::Watch => "watch",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        PlayerFlag::ALL.into_iter().find(|flag| flag.as_str() == value)
+    }
+}
+
+impl fmt::Display for PlayerFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayerFlag::Avoid => write!(f, "Avoid"),
+            PlayerFlag::FriendOfFriend => write!(f, "Friend of friend"),
+            PlayerFlag::Watch => write!(f, "Watch"),
+        }
+    }
+}
+
+// One row per match, rows recorded before this table existed are backfilled from matchhistory
+// without a queue, server or puuid
+#[derive(Turbosql, Default, Debug, Clone)]
+pub struct Match {
+    pub rowid: Option<i64>,
+    pub match_id: String,
+    pub map_id: String,
+    pub gamemode_id: String,
+    pub queue_id: String,
+    pub server: String,
+    pub game_pod: String,
+    pub start_time: i64,
+    // The player Val+ was running for
+    pub puuid: String,
+}
+
+// One row per player per match, the match itself is in Match.
+// The old map_id, gamemode_id and match_time columns are still in the table but no longer written
 #[derive(Turbosql, Default, Debug, Clone)]
 pub struct MatchHistory {
     pub rowid: Option<i64>,
     pub uuid: String,
     pub match_id: String,
-    pub map_id: String,
-    pub gamemode_id: Option<String>,
     pub enemy: Option<bool>,
     pub agent_id: String,
-    pub match_time: i64,
+}
+
+// What we think of a player, one per puuid
+#[derive(Turbosql, Default, Debug, Clone, PartialEq)]
+pub struct PlayerNote {
+    pub rowid: Option<i64>,
+    pub uuid: String,
+    pub text: String,
+    // Comma separated, e.g. "thrower, duo with X"
+    pub tags: String,
+    // 1 to 5, None when unrated
+    pub rating: Option<i64>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl PlayerNote {
+    pub fn tags(&self) -> Vec<&str> {
+        self.tags.split(',').map(|tag| tag.trim()).filter(|tag| !tag.is_empty()).collect()
+    }
+}
+
+// A team's final result in a finished match, from pd match-details
+#[derive(Turbosql, Default, Debug, Clone)]
+pub struct MatchResult {
+    pub rowid: Option<i64>,
+    pub match_id: String,
+    pub team_id: String,
+    pub won: bool,
+    pub rounds_won: i64,
+    pub rounds_played: i64,
+}
+
+// Result lookups of a match that failed, so one that never resolves stops being retried
+#[derive(Turbosql, Default, Debug, Clone)]
+pub struct ResultLookup {
+    pub rowid: Option<i64>,
+    pub match_id: String,
+    pub failures: i64,
+}
+
+// A player's scoreline in a finished match
+#[derive(Turbosql, Default, Debug, Clone)]
+pub struct MatchPlayerStats {
+    pub rowid: Option<i64>,
+    pub match_id: String,
+    pub uuid: String,
+    pub team_id: String,
+    pub kills: i64,
+    pub deaths: i64,
+    pub assists: i64,
+    pub score: i64,
+}
+
+// Everything from match-details for one match, written by record_results
+#[derive(Default, Debug, Clone)]
+pub struct MatchOutcome {
+    pub match_id: String,
+    pub teams: Vec<MatchResult>,
+    pub players: Vec<MatchPlayerStats>,
+}
+
+// Finished matches with a player, won and lost from the side of whoever recorded the match
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct WinLoss {
+    pub with_wins: i64,
+    pub with_losses: i64,
+    pub against_wins: i64,
+    pub against_losses: i64,
+}
+
+impl WinLoss {
+    pub fn is_empty(&self) -> bool {
+        *self == WinLoss::default()
+    }
+}
+
+// "3–1 with, 0–2 against"
+impl fmt::Display for WinLoss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}–{} with, {}–{} against", self.with_wins, self.with_losses, self.against_wins, self.against_losses)
+    }
+}
+
+// How often a map or agent came up with a player.
+// Backfilled rows without a side count towards matches but neither side, like EncounterStats
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct SideCounts {
+    pub id: String,
+    pub matches: i64,
+    pub together: i64,
+    pub against: i64,
+}
+
+// Everything recorded about playing with or against a player, see encounter_stats
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct EncounterStats {
+    pub together: i64,
+    pub against: i64,
+    // Most played first, by map_id
+    pub maps: Vec<SideCounts>,
+    // Most played first, by agent_id
+    pub agents: Vec<SideCounts>,
+    pub first_seen: Option<i64>,
+    pub last_seen: Option<i64>,
+    pub win_loss: WinLoss,
+    // Latest finished matches in a row with the same result, positive for wins and negative for losses
+    pub streak: i64,
+}
+
+impl EncounterStats {
+    fn new(history: &[PlayerMatch], results: &[EncounterResult]) -> Self {
+        let mut stats = EncounterStats::default();
+
+        for log in history {
+            match log.participant.enemy {
+                Some(true) => stats.against += 1,
+                Some(false) => stats.together += 1,
+                None => {}
+            }
+        }
+
+        stats.maps = side_counts(history, |x| &x.details.map_id);
+        stats.agents = side_counts(history, |x| &x.participant.agent_id);
+
+        // Backfilled matches can be missing a start time
+        let seen = history.iter().map(|x| x.details.start_time).filter(|x| *x > 0);
+        stats.first_seen = seen.clone().min();
+        stats.last_seen = seen.max();
+
+        for result in results {
+            match (result.together, result.won) {
+                (true, true) => stats.win_loss.with_wins += 1,
+                (true, false) => stats.win_loss.with_losses += 1,
+                (false, true) => stats.win_loss.against_wins += 1,
+                (false, false) => stats.win_loss.against_losses += 1,
+            }
+        }
+
+        let mut latest: Vec<&EncounterResult> = results.iter().collect();
+        latest.sort_by_key(|x| std::cmp::Reverse(x.start_time));
+
+        if let Some(last) = latest.first() {
+            let count = latest.iter().take_while(|x| x.won == last.won).count() as i64;
+            stats.streak = if last.won { count } else { -count };
+        }
+
+        stats
+    }
+}
+
+// Most played first, matches without the key are left out
+fn side_counts(history: &[PlayerMatch], key: impl Fn(&PlayerMatch) -> &str) -> Vec<SideCounts> {
+    let mut counts: HashMap<&str, SideCounts> = HashMap::new();
+
+    for log in history {
+        let id = key(log);
+
+        if id.is_empty() {
+            continue
+        }
+
+        let count = counts.entry(id).or_insert_with(|| SideCounts { id: id.to_string(), ..Default::default() });
+        count.matches += 1;
+
+        match log.participant.enemy {
+            Some(true) => count.against += 1,
+            Some(false) => count.together += 1,
+            None => {}
+        }
+    }
+
+    let mut counts: Vec<SideCounts> = counts.into_values().collect();
+    counts.sort_by(|a, b| b.matches.cmp(&a.matches).then_with(|| a.id.cmp(&b.id)));
+
+    counts
+}
+
+// Everything seen in one loaded match, written by record_match
+#[derive(Default, Debug, Clone)]
+pub struct MatchSnapshot {
+    pub details: Match,
+    pub participants: Vec<Participant>,
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct Participant {
+    pub uuid: String,
+    pub name: String,
+    pub tag: String,
+    pub agent_id: String,
+    pub enemy: bool,
+}
+
+// A player's row joined with the match it belongs to
+#[derive(Default, Debug, Clone)]
+pub struct PlayerMatch {
+    pub participant: MatchHistory,
+    pub details: Match,
 }
 
 #[derive(Turbosql, Default, Debug, Clone)]
@@ -30,135 +289,1022 @@ pub struct NameHistory {
     pub name_time: Option<i64>,
 }
 
-fn user_exits(uuid: &String) -> bool {
-    if select!(UserDatabase "WHERE uuid =" uuid).is_ok() {
-        return true
+// A recorded match with everyone in it, see match_log
+#[derive(Default, Debug, Clone)]
+pub struct LoggedMatch {
+    pub details: Match,
+    pub participants: Vec<LoggedParticipant>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct LoggedParticipant {
+    pub uuid: String,
+    // Latest recorded name
+    pub name: String,
+    pub tag: String,
+    pub agent_id: String,
+    pub enemy: Option<bool>,
+}
+
+// A player found by search_players
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    pub uuid: String,
+    // Latest recorded name
+    pub name: String,
+    pub tag: String,
+    // The older name#tag that matched, when it wasn't the latest one
+    pub matched: Option<String>,
+    pub times_played: i64,
+    pub last_played: i64,
+}
+
+#[derive(Debug)]
+pub enum DbError {
+    NotFound,
+    // The row is already recorded
+    Duplicate,
+    Sqlite(turbosql::Error),
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::NotFound => write!(f, "not found"),
+            DbError::Duplicate => write!(f, "already recorded"),
+            DbError::Sqlite(err) => write!(f, "database error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for DbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DbError::Sqlite(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<turbosql::Error> for DbError {
+    fn from(err: turbosql::Error) -> Self {
+        match err {
+            turbosql::Error::Rusqlite(turbosql::rusqlite::Error::QueryReturnedNoRows) => DbError::NotFound,
+            // Only uniqueness means the row exists, NOT NULL or CHECK failures are malformed data
+            turbosql::Error::Rusqlite(turbosql::rusqlite::Error::SqliteFailure(failure, _))
+                if matches!(failure.extended_code, ffi::SQLITE_CONSTRAINT_UNIQUE | ffi::SQLITE_CONSTRAINT_PRIMARYKEY) => DbError::Duplicate,
+            err => DbError::Sqlite(err),
+        }
     }
-    return false
 }
-fn add_user(uuid: &String) -> Result<(), ()> {
-    let res = UserDatabase {
-        uuid: uuid.clone(),
+
+fn user_exits(uuid: &String) -> Result<bool, DbError> {
+    Ok(select!(Option<UserDatabase> "WHERE uuid =" uuid)?.is_some())
+}
+
+fn add_user(uuid: &str) -> Result<(), DbError> {
+    UserDatabase {
+        uuid: uuid.to_string(),
         times_played: 0,
         last_played: 0,
         ..Default::default()
-    }.insert();
+    }.insert()?;
 
-    if res.is_ok() {
-        return Ok(())
+    Ok(())
+}
+
+pub fn get_user(uuid: String) -> Result<UserDatabase, DbError> {
+    select!(Option<UserDatabase> "WHERE uuid =" uuid)?.ok_or(DbError::NotFound)
+}
+
+// times_played and last_played are derived from matchhistory, this brings one user's row back in line with it
+pub fn sync_user(uuid: String) -> Result<(), DbError> {
+    if !user_exits(&uuid)? {
+        add_user(&uuid)?;
     }
-    Err(())
+
+    update_counts(&uuid)?;
+
+    Ok(())
 }
 
-pub fn get_user(uuid: String) -> Result<UserDatabase, ()> {
-    if let Ok(user) = select!(UserDatabase "WHERE uuid =" uuid) {
-        return Ok(user)
+fn update_counts(uuid: &String) -> Result<usize, turbosql::Error> {
+    execute!(
+        "UPDATE userdatabase SET times_played = (SELECT COUNT(*) FROM matchhistory WHERE uuid =" uuid "),"
+        "last_played = COALESCE((SELECT MAX(match.start_time) FROM matchhistory JOIN match ON match.match_id = matchhistory.match_id WHERE matchhistory.uuid =" uuid "), 0)"
+        "WHERE uuid =" uuid
+    )
+}
+
+// Recomputes every user from matchhistory, for counts that drifted before they were derived.
+// Returns the number of users updated
+pub fn repair_users() -> Result<usize, DbError> {
+    execute!("INSERT INTO userdatabase (uuid) SELECT DISTINCT uuid FROM matchhistory WHERE uuid NOT IN (SELECT uuid FROM userdatabase)")?;
+
+    let updated = execute!(
+        "UPDATE userdatabase SET times_played = (SELECT COUNT(*) FROM matchhistory WHERE matchhistory.uuid = userdatabase.uuid),"
+        "last_played = COALESCE((SELECT MAX(match.start_time) FROM matchhistory JOIN match ON match.match_id = matchhistory.match_id WHERE matchhistory.uuid = userdatabase.uuid), 0)"
+    )?;
+
+    Ok(updated)
+}
+
+pub fn get_user_name_history(uuid: String) -> Result<Vec<NameHistory>, DbError> {
+    Ok(select!(Vec<NameHistory> "WHERE uuid=" uuid)?)
+}
+
+// Players whose current or past name matches, best matches and most played first.
+// "name", "name#tag" and "#tag" match by prefix, then anywhere in the name, then by letters in order.
+// A full puuid finds that player directly
+pub fn search_players(query: &str, limit: usize) -> Result<Vec<SearchResult>, DbError> {
+    let query = query.trim();
+
+    if query.is_empty() {
+        return Ok(Vec::new())
+    }
+
+    let (names, puuid) = if is_puuid(query) {
+        let uuid = query.to_lowercase();
+        (select!(Vec<NameHistory> "WHERE uuid =" uuid)?, Some(uuid))
+    } else {
+        (select!(Vec<NameHistory>)?, None)
+    };
+
+    let mut players: HashMap<&str, Vec<&NameHistory>> = HashMap::new();
+
+    for name in &names {
+        players.entry(name.uuid.as_str()).or_default().push(name);
+    }
+
+    let mut found: Vec<(u8, SearchResult)> = Vec::new();
+
+    for (uuid, names) in players {
+        let latest = names.iter().max_by_key(|x| (x.name_time.unwrap_or(0), x.rowid)).unwrap();
+
+        let best = match puuid {
+            Some(_) => Some((0, *latest)),
+            None => names.iter().filter_map(|x| Some((search_score(query, &x.name, &x.tag)?, *x))).min_by_key(|x| x.0),
+        };
+
+        let Some((score, matched)) = best else {
+            continue
+        };
+
+        found.push((score, SearchResult {
+            uuid: uuid.to_string(),
+            name: latest.name.clone(),
+            tag: latest.tag.clone(),
+            matched: (matched.rowid != latest.rowid).then(|| format!("{}#{}", matched.name, matched.tag)),
+            ..Default::default()
+        }));
+    }
+
+    // Known from matches without any recorded name
+    if let Some(uuid) = &puuid {
+        if found.is_empty() && user_exits(uuid)? {
+            found.push((0, SearchResult { uuid: uuid.clone(), ..Default::default() }));
+        }
+    }
+
+    // Counts for just the matched players in one query, the uuids go in as a JSON array
+    let uuids = serde_json::to_string(&found.iter().map(|(_, x)| x.uuid.as_str()).collect::<Vec<&str>>()).unwrap_or_default();
+    let users = select!(Vec<UserDatabase> "WHERE uuid IN (SELECT value FROM json_each(" uuids "))")?;
+    let users: HashMap<&str, &UserDatabase> = users.iter().map(|x| (x.uuid.as_str(), x)).collect();
+
+    for (_, result) in found.iter_mut() {
+        if let Some(user) = users.get(result.uuid.as_str()) {
+            result.times_played = user.times_played;
+            result.last_played = user.last_played;
+        }
     }
 
-    Err(())
+    found.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.times_played.cmp(&a.1.times_played)).then_with(|| a.1.name.cmp(&b.1.name)));
+    found.truncate(limit);
+
+    Ok(found.into_iter().map(|(_, result)| result).collect())
 }
 
-pub fn update_user(uuid: String) -> Result<(), ()> {
-    if !user_exits(&uuid) {
-        if let Err(_) = add_user(&uuid) {
-            return Err(())
+// Recorded matches newest first, `offset` and `limit` page through them
+pub fn match_log(offset: i64, limit: i64) -> Result<Vec<LoggedMatch>, DbError> {
+    let matches = select!(Vec<Match> "ORDER BY start_time DESC, rowid DESC LIMIT" limit "OFFSET" offset)?;
+
+    if matches.is_empty() {
+        return Ok(Vec::new())
+    }
+
+    let participants = select!(Vec<MatchHistory> "WHERE match_id IN (SELECT match_id FROM match ORDER BY start_time DESC, rowid DESC LIMIT" limit "OFFSET" offset ")")?;
+    let names = select!(Vec<NameHistory> "WHERE uuid IN (SELECT uuid FROM matchhistory WHERE match_id IN (SELECT match_id FROM match ORDER BY start_time DESC, rowid DESC LIMIT" limit "OFFSET" offset "))")?;
+
+    let mut latest: HashMap<&str, &NameHistory> = HashMap::new();
+
+    for name in &names {
+        let newer = latest.get(name.uuid.as_str()).is_none_or(|x| (name.name_time.unwrap_or(0), name.rowid) > (x.name_time.unwrap_or(0), x.rowid));
+
+        if newer {
+            latest.insert(&name.uuid, name);
         }
     }
 
-    if let Ok(a) = execute!("UPDATE userdatabase SET times_played = times_played + 1, last_played =" SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() "WHERE uuid=" uuid) {
-        return Ok(())
+    Ok(matches.into_iter().map(|details| {
+        let participants = participants.iter().filter(|x| x.match_id == details.match_id).map(|x| {
+            let name = latest.get(x.uuid.as_str());
+
+            LoggedParticipant {
+                uuid: x.uuid.clone(),
+                name: name.map(|x| x.name.clone()).unwrap_or_default(),
+                tag: name.map(|x| x.tag.clone()).unwrap_or_default(),
+                agent_id: x.agent_id.clone(),
+                enemy: x.enemy,
+            }
+        }).collect();
+
+        LoggedMatch { details, participants }
+    }).collect())
+}
+
+// Latest players who shared a match with puuid, the player Val+ is running for,
+// as search results so they list the same way
+pub fn recent_players(puuid: &str, limit: i64) -> Result<Vec<SearchResult>, DbError> {
+    // Each player's latest name comes from the same query, joined on its namehistory rowid
+    Ok(select!(Vec<SearchResult>
+        "userdatabase.uuid AS uuid, COALESCE(latest.name, '') AS name, COALESCE(latest.tag, '') AS tag, times_played, last_played"
+        "FROM userdatabase LEFT JOIN namehistory AS latest ON latest.rowid ="
+        "(SELECT rowid FROM namehistory WHERE namehistory.uuid = userdatabase.uuid ORDER BY name_time DESC, rowid DESC LIMIT 1)"
+        "WHERE userdatabase.uuid !=" puuid "AND times_played > 0"
+        "AND userdatabase.uuid IN (SELECT uuid FROM matchhistory WHERE match_id IN (SELECT match_id FROM matchhistory WHERE uuid =" puuid "))"
+        "ORDER BY last_played DESC LIMIT" limit
+    )?)
+}
+
+pub fn get_latest_name(uuid: String) -> Result<Option<NameHistory>, DbError> {
+    Ok(select!(Option<NameHistory> "WHERE uuid =" uuid "ORDER BY name_time DESC, rowid DESC LIMIT 1")?)
+}
+
+fn is_puuid(query: &str) -> bool {
+    query.len() == 36 && query.char_indices().all(|(i, c)| if [8, 13, 18, 23].contains(&i) { c == '-' } else { c.is_ascii_hexdigit() })
+}
+
+// Lower is a closer match, None when it doesn't match at all
+fn search_score(query: &str, name: &str, tag: &str) -> Option<u8> {
+    let (query_name, query_tag) = match query.split_once('#') {
+        Some((query_name, query_tag)) => (query_name.trim(), Some(query_tag.trim())),
+        None => (query, None),
+    };
+
+    if let Some(query_tag) = query_tag {
+        if !tag.to_lowercase().starts_with(&query_tag.to_lowercase()) {
+            return None
+        }
+
+        // "#tag" on its own
+        if query_name.is_empty() {
+            return Some(1)
+        }
     }
 
-    Err(())
+    let (query_name, name) = (query_name.to_lowercase(), name.to_lowercase());
+
+    if name == query_name {
+        Some(0)
+    } else if name.starts_with(&query_name) {
+        Some(1)
+    } else if name.contains(&query_name) {
+        Some(2)
+    } else {
+        // Letters in order, e.g. "jtdf" for "jett diff"
+        let mut letters = name.chars();
+        query_name.chars().all(|c| letters.any(|x| x == c)).then_some(3)
+    }
 }
 
-pub fn add_user_full(uuid: String, times_played: i64, last_played: i64) -> Result<(), ()> {
-    let res = UserDatabase {
-        uuid: uuid.clone(),
-        times_played,
-        last_played,
+pub fn add_new_name(uuid: String, name: String, tag: String) -> Result<(), DbError> {
+    if name_exists(&uuid, &name, &tag)? {
+        return Err(DbError::Duplicate)
+    }
+
+    NameHistory {
+        uuid,
+        name,
+        tag,
+        name_time: Some(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64),
         ..Default::default()
-    }.insert();
+    }.insert()?;
 
-    if res.is_ok() {
-        return Ok(())
+    Ok(())
+}
+
+fn name_exists(uuid: &String, name: &String, tag: &String) -> Result<bool, DbError> {
+    Ok(select!(Option<NameHistory> "WHERE uuid=" uuid "AND name=" name "AND tag=" tag)?.is_some())
+}
+
+pub fn get_user_match_history(uuid: String) -> Result<Vec<PlayerMatch>, DbError> {
+    let participants = select!(Vec<MatchHistory> "WHERE uuid=" uuid)?;
+
+    if participants.is_empty() {
+        return Ok(Vec::new())
     }
-    Err(())
+
+    let matches = select!(Vec<Match> "WHERE match_id IN (SELECT match_id FROM matchhistory WHERE uuid=" uuid ")")?;
+    let mut matches: HashMap<String, Match> = matches.into_iter().map(|x| (x.match_id.clone(), x)).collect();
+
+    Ok(participants.into_iter().map(|participant| {
+        let details = matches.remove(&participant.match_id).unwrap_or_else(|| Match {
+            match_id: participant.match_id.clone(),
+            ..Default::default()
+        });
+
+        PlayerMatch { participant, details }
+    }).collect())
+}
+
+pub fn add_match(record: Match) -> Result<(), DbError> {
+    if select!(Option<Match> "WHERE match_id=" record.match_id)?.is_some() {
+        return Err(DbError::Duplicate)
+    }
+
+    record.insert()?;
+
+    Ok(())
+}
+
+pub fn add_new_match(uuid: String, match_id: String, agent_id: String, enemy: bool) -> Result<(), DbError> {
+    if match_exists(&uuid, &match_id)? {
+        return Err(DbError::Duplicate)
+    }
+
+    MatchHistory {
+        uuid,
+        match_id,
+        agent_id,
+        enemy: Some(enemy),
+        ..Default::default()
+    }.insert()?;
+
+    Ok(())
+}
+
+fn match_exists(uuid: &String, match_id: &String) -> Result<bool, DbError> {
+    Ok(select!(Option<MatchHistory> "WHERE uuid=" uuid "AND match_id=" match_id)?.is_some())
+}
+
+// Writes the match with all its participants, names and counters in one transaction.
+// Rows that are already recorded are left alone, and if any write fails nothing from the match is kept
+pub fn record_match(snapshot: MatchSnapshot) -> Result<(), DbError> {
+    execute!("BEGIN IMMEDIATE")?;
+
+    let res = write_snapshot(&snapshot).and_then(|_| Ok(execute!("COMMIT")?));
+
+    if let Err(err) = res {
+        let _ = execute!("ROLLBACK");
+        return Err(err)
+    }
+
+    Ok(())
 }
 
-pub fn get_user_name_history(uuid: String) -> Result<Vec<NameHistory>, ()>{
-    if select!(NameHistory "WHERE uuid=" uuid).is_ok() {
-        if let Ok(history) = select!(Vec<NameHistory> "WHERE uuid=" uuid) {
-            return Ok(history);
+fn write_snapshot(snapshot: &MatchSnapshot) -> Result<(), DbError> {
+    let match_id = &snapshot.details.match_id;
+    let time_now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+
+    if select!(Option<Match> "WHERE match_id =" match_id)?.is_none() {
+        snapshot.details.insert()?;
+    }
+
+    for player in &snapshot.participants {
+        if !match_exists(&player.uuid, match_id)? {
+            MatchHistory {
+                uuid: player.uuid.clone(),
+                match_id: match_id.clone(),
+                agent_id: player.agent_id.clone(),
+                enemy: Some(player.enemy),
+                ..Default::default()
+            }.insert()?;
+        }
+
+        if !name_exists(&player.uuid, &player.name, &player.tag)? {
+            NameHistory {
+                uuid: player.uuid.clone(),
+                name: player.name.clone(),
+                tag: player.tag.clone(),
+                name_time: Some(time_now),
+                ..Default::default()
+            }.insert()?;
         }
+
+        if !user_exits(&player.uuid)? {
+            add_user(&player.uuid)?;
+        }
+
+        update_counts(&player.uuid)?;
     }
 
-    Err(())
+    Ok(())
 }
 
-pub fn add_new_name(uuid: String, name: String, tag: String) -> Result<(), ()> {
-    if !name_exists(&uuid, &name, &tag) {
-        let res = NameHistory {
-            uuid,
-            name,
-            tag,
-            name_time: Some(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64),
-            ..Default::default()
-        }.insert();
+// None clears the flag along with its reason
+pub fn set_flag(uuid: String, flag: Option<PlayerFlag>, reason: String) -> Result<(), DbError> {
+    if !user_exits(&uuid)? {
+        add_user(&uuid)?;
+    }
+
+    let (flag, reason) = match flag {
+        Some(flag) => (flag.as_str(), reason.trim().to_string()),
+        None => ("", String::new()),
+    };
+
+    execute!("UPDATE userdatabase SET flag =" flag ", flag_reason =" reason "WHERE uuid =" uuid)?;
+
+    Ok(())
+}
 
-        if res.is_ok() {
-            return Ok(())
+// Counts a failed result lookup, after MAX_LOOKUP_FAILURES the match is no longer pending
+pub fn result_lookup_failed(match_id: &str) -> Result<(), DbError> {
+    match select!(Option<ResultLookup> "WHERE match_id =" match_id)? {
+        Some(lookup) => {
+            execute!("UPDATE resultlookup SET failures =" lookup.failures + 1 "WHERE match_id =" match_id)?;
+        }
+        None => {
+            ResultLookup { match_id: match_id.to_string(), failures: 1, ..Default::default() }.insert()?;
         }
     }
 
-    Err(())
+    Ok(())
 }
 
-fn name_exists(uuid: &String, name: &String, tag: &String) -> bool {
-    if select!(NameHistory "WHERE uuid=" uuid "AND name=" name "AND tag=" tag).is_ok() {
-        return true
+// Writes the results of a finished match in one transaction, matches that already have results are skipped
+pub fn record_results(outcome: MatchOutcome) -> Result<(), DbError> {
+    execute!("BEGIN IMMEDIATE")?;
+
+    let res = write_outcome(&outcome).and_then(|_| Ok(execute!("COMMIT")?));
+
+    if let Err(err) = res {
+        let _ = execute!("ROLLBACK");
+        return Err(err)
     }
-    return false
+
+    Ok(())
 }
 
+fn write_outcome(outcome: &MatchOutcome) -> Result<(), DbError> {
+    let match_id = &outcome.match_id;
 
-pub fn get_user_match_history(uuid: String) -> Result<Vec<MatchHistory>, ()>{
-    if select!(MatchHistory "WHERE uuid=" uuid).is_ok() {
-        if let Ok(history) = select!(Vec<MatchHistory> "WHERE uuid=" uuid) {
-            return Ok(history);
-        }
+    if select!(Option<MatchResult> "WHERE match_id =" match_id)?.is_some() {
+        return Ok(())
     }
 
-    Err(())
+    for team in &outcome.teams {
+        MatchResult { rowid: None, match_id: match_id.clone(), ..team.clone() }.insert()?;
+    }
+
+    for player in &outcome.players {
+        MatchPlayerStats { rowid: None, match_id: match_id.clone(), ..player.clone() }.insert()?;
+    }
+
+    Ok(())
 }
 
-pub fn add_new_match(uuid: String, match_id: String, map_id: String, gamemode_id: String, agent_id: String, enemy: bool, time_played: i64) -> Result<(), ()> {
-    if !match_exists(&uuid, &match_id) {
-        let res = MatchHistory {
-            uuid,
-            match_id,
-            match_time: time_played,
-            map_id,
-            gamemode_id: Some(gamemode_id),
-            agent_id,
-            enemy: Some(enemy),
-            ..Default::default()
-        }.insert();
+// Failed lookups before a match stops counting as pending
+const MAX_LOOKUP_FAILURES: i64 = 3;
+
+// Matches recorded for puuid since the given time that don't have results yet, newest first
+pub fn pending_results(puuid: &str, since: i64, limit: i64) -> Result<Vec<String>, DbError> {
+    let matches = select!(Vec<Match>
+        "WHERE puuid =" puuid "AND start_time >=" since "AND match_id NOT IN (SELECT match_id FROM matchresult)"
+        "AND match_id NOT IN (SELECT match_id FROM resultlookup WHERE failures >=" MAX_LOOKUP_FAILURES ")"
+        "ORDER BY start_time DESC LIMIT" limit
+    )?;
 
-        if res.is_ok() {
-            return Ok(())
-        } else {
-            println!("{:?}", res);
+    Ok(matches.into_iter().map(|x| x.match_id).collect())
+}
+
+// A finished match with a player, from the side of whoever recorded it
+struct EncounterResult {
+    start_time: i64,
+    together: bool,
+    won: bool,
+}
+
+fn encounter_results(uuid: &str) -> Result<Vec<EncounterResult>, DbError> {
+    let theirs = select!(Vec<MatchPlayerStats> "WHERE uuid =" uuid)?;
+
+    // The recording player's own line in each of those matches
+    let ours = select!(Vec<MatchPlayerStats> "WHERE uuid !=" uuid "AND match_id IN (SELECT match_id FROM matchplayerstats WHERE uuid =" uuid ") AND uuid = (SELECT puuid FROM match WHERE match.match_id = matchplayerstats.match_id)")?;
+    let results = select!(Vec<MatchResult> "WHERE match_id IN (SELECT match_id FROM matchplayerstats WHERE uuid =" uuid ")")?;
+    let matches = select!(Vec<Match> "WHERE match_id IN (SELECT match_id FROM matchplayerstats WHERE uuid =" uuid ")")?;
+
+    Ok(theirs.into_iter().filter_map(|their| {
+        let our = ours.iter().find(|x| x.match_id == their.match_id)?;
+        let result = results.iter().find(|x| x.match_id == our.match_id && x.team_id == our.team_id)?;
+
+        Some(EncounterResult {
+            start_time: matches.iter().find(|x| x.match_id == their.match_id).map_or(0, |x| x.start_time),
+            together: their.team_id == our.team_id,
+            won: result.won,
+        })
+    }).collect())
+}
+
+pub fn encounter_stats(uuid: String) -> Result<EncounterStats, DbError> {
+    let history = get_user_match_history(uuid.clone())?;
+    let results = encounter_results(&uuid)?;
+
+    Ok(EncounterStats::new(&history, &results))
+}
+
+pub fn get_note(uuid: String) -> Result<PlayerNote, DbError> {
+    select!(Option<PlayerNote> "WHERE uuid =" uuid)?.ok_or(DbError::NotFound)
+}
+
+// Creates the player's note or replaces the existing one, returning it as stored
+pub fn save_note(note: PlayerNote) -> Result<PlayerNote, DbError> {
+    let time_now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+
+    let mut stored = match get_note(note.uuid.clone()) {
+        Ok(existing) => PlayerNote { rowid: existing.rowid, created_at: existing.created_at, ..note },
+        Err(DbError::NotFound) => PlayerNote { rowid: None, created_at: time_now, ..note },
+        Err(err) => return Err(err),
+    };
+
+    stored.rating = stored.rating.map(|rating| rating.clamp(1, 5));
+    stored.updated_at = time_now;
+
+    match stored.rowid {
+        Some(_) => {
+            stored.update()?;
         }
+        None => stored.rowid = Some(stored.insert()?),
     }
 
-    Err(())
+    Ok(stored)
+}
+
+pub fn delete_note(uuid: String) -> Result<(), DbError> {
+    get_note(uuid)?.delete()?;
+
+    Ok(())
+}
+
+// Held for the length of a test, which gets the database to itself
+#[cfg(test)]
+pub struct TestDb {
+    _lock: std::sync::MutexGuard<'static, ()>,
 }
 
-fn match_exists(uuid: &String, match_id: &String) -> bool {
-    if select!(MatchHistory "WHERE uuid=" uuid "AND match_id=" match_id).is_ok() {
-        return true
+// Gives the calling test an empty database. turbosql opens one database per process with a
+// connection per thread, so tests take turns on a file that is cleared for each of them
+#[cfg(test)]
+pub fn use_test_db() -> TestDb {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    INIT.call_once(|| {
+        let path = std::env::temp_dir().join(format!("valplus_test_{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        turbosql::set_db_path(&path).unwrap();
+    });
+
+    // A test that failed while holding the lock leaves nothing that clearing doesn't undo
+    let guard = LOCK.lock().unwrap_or_else(|err| err.into_inner());
+
+    // Runs the migrations before the tables are listed
+    select!(Option<UserDatabase> "LIMIT 1").unwrap();
+
+    let db = turbosql::rusqlite::Connection::open(turbosql::db_path()).unwrap();
+    let tables: Vec<String> = db.prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name NOT LIKE '%turbosql%'").unwrap()
+        .query_map([], |row| row.get(0)).unwrap()
+        .map(|name| name.unwrap())
+        .collect();
+
+    for table in tables {
+        db.execute(&format!("DELETE FROM {}", table), []).unwrap();
     }
-    return false
+
+    TestDb { _lock: guard }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_are_derived_from_match_history() {
+        let _db = use_test_db();
+
+        let uuid = "derived-count-player".to_string();
+
+        for (match_id, start_time) in [("derived-count-1", 100), ("derived-count-2", 300)] {
+            add_match(Match { match_id: match_id.to_string(), start_time, ..Default::default() }).unwrap();
+            add_new_match(uuid.clone(), match_id.to_string(), String::new(), false).unwrap();
+            sync_user(uuid.clone()).unwrap();
+        }
+
+        // Seeing the same match again changes nothing
+        assert!(matches!(add_new_match(uuid.clone(), "derived-count-2".to_string(), String::new(), false), Err(DbError::Duplicate)));
+        sync_user(uuid.clone()).unwrap();
+
+        let user = get_user(uuid.clone()).unwrap();
+        assert_eq!((user.times_played, user.last_played), (2, 300));
+
+        // Counts that drifted are put back by the repair
+        execute!("UPDATE userdatabase SET times_played = 99 WHERE uuid =" uuid).unwrap();
+        assert!(repair_users().unwrap() >= 1);
+        assert_eq!(get_user(uuid).unwrap().times_played, 2);
+    }
+
+    fn snapshot(match_id: &str, uuids: &[&str]) -> MatchSnapshot {
+        MatchSnapshot {
+            details: Match { match_id: match_id.to_string(), start_time: 500, ..Default::default() },
+            participants: uuids.iter().map(|uuid| Participant {
+                uuid: uuid.to_string(),
+                name: "name".to_string(),
+                tag: "tag".to_string(),
+                ..Default::default()
+            }).collect(),
+        }
+    }
+
+    #[test]
+    fn records_match_once() {
+        let _db = use_test_db();
+
+        let record = snapshot("snapshot-once", &["snapshot-once-a", "snapshot-once-b"]);
+
+        record_match(record.clone()).unwrap();
+        record_match(record).unwrap();
+
+        let user = get_user("snapshot-once-a".to_string()).unwrap();
+        assert_eq!((user.times_played, user.last_played), (1, 500));
+        assert_eq!(get_user_name_history("snapshot-once-b".to_string()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn failed_write_keeps_nothing() {
+        let _db = use_test_db();
+
+        // Make the second player's name insert fail after the match and first player are written
+        execute!("CREATE TEMP TRIGGER fail_name BEFORE INSERT ON namehistory WHEN NEW.uuid = 'snapshot-fail-b' BEGIN SELECT RAISE(ABORT, 'fail'); END").unwrap();
+
+        assert!(record_match(snapshot("snapshot-fail", &["snapshot-fail-a", "snapshot-fail-b"])).is_err());
+
+        execute!("DROP TRIGGER IF EXISTS fail_name").unwrap();
+
+        assert!(select!(Option<Match> "WHERE match_id = 'snapshot-fail'").unwrap().is_none());
+        assert!(get_user_match_history("snapshot-fail-a".to_string()).unwrap().is_empty());
+        assert!(matches!(get_user("snapshot-fail-a".to_string()), Err(DbError::NotFound)));
+    }
+
+    // Lookups against a year's worth of imported history: 20k matches with 5 of 5k players in each
+    #[test]
+    fn lookups_use_indexes_at_100k_rows() {
+        let _db = use_test_db();
+
+        execute!(
+            "WITH RECURSIVE n(i) AS (SELECT 0 UNION ALL SELECT i + 1 FROM n WHERE i < 99999)"
+            "INSERT INTO matchhistory (uuid, match_id, agent_id, enemy) SELECT 'bench-' || (i % 5000), 'bench-match-' || (i / 5), '', i % 2 FROM n"
+        ).unwrap();
+        execute!(
+            "WITH RECURSIVE n(i) AS (SELECT 0 UNION ALL SELECT i + 1 FROM n WHERE i < 19999)"
+            "INSERT INTO match (match_id, start_time) SELECT 'bench-match-' || i, i FROM n"
+        ).unwrap();
+        execute!(
+            "WITH RECURSIVE n(i) AS (SELECT 0 UNION ALL SELECT i + 1 FROM n WHERE i < 4999)"
+            "INSERT INTO namehistory (uuid, name, tag) SELECT 'bench-' || i, 'name', 'tag' FROM n"
+        ).unwrap();
+
+        let started = std::time::Instant::now();
+
+        for i in 0..500 {
+            let uuid = format!("bench-{}", i * 10);
+
+            assert_eq!(get_user_match_history(uuid.clone()).unwrap().len(), 20);
+            get_user_name_history(uuid.clone()).unwrap();
+            sync_user(uuid.clone()).unwrap();
+            assert!(match_exists(&uuid, &format!("bench-match-{}", i * 2)).unwrap());
+        }
+
+        println!("500 players looked up and synced in {:?}", started.elapsed());
+
+        // Query plans are read over a second connection, turbosql doesn't expose them
+        let db = turbosql::rusqlite::Connection::open(turbosql::db_path()).unwrap();
+        let plan = |sql: &str| -> String {
+            db.prepare(&format!("EXPLAIN QUERY PLAN {}", sql)).unwrap()
+                .query_map([], |row| row.get::<_, String>(3)).unwrap()
+                .map(|detail| detail.unwrap())
+                .collect::<Vec<_>>()
+                .join("; ")
+        };
+
+        for sql in [
+            "SELECT * FROM userdatabase WHERE uuid = 'bench-1'",
+            "SELECT * FROM matchhistory WHERE uuid = 'bench-1' AND match_id = 'bench-match-0'",
+            "SELECT * FROM namehistory WHERE uuid = 'bench-1' AND name = 'name' AND tag = 'tag'",
+            "SELECT * FROM match WHERE match_id IN (SELECT match_id FROM matchhistory WHERE uuid = 'bench-1')",
+        ] {
+            let plan = plan(sql);
+            assert!(!plan.contains("SCAN"), "{} -> {}", sql, plan);
+        }
+    }
+
+    #[test]
+    fn note_crud() {
+        let _db = use_test_db();
+
+        let uuid = "note-player".to_string();
+        assert!(matches!(get_note(uuid.clone()), Err(DbError::NotFound)));
+
+        let created = save_note(PlayerNote {
+            uuid: uuid.clone(),
+            text: "insta locks jett".to_string(),
+            tags: "thrower, , duo with X".to_string(),
+            rating: Some(9),
+            ..Default::default()
+        }).unwrap();
+
+        assert_eq!(created.rating, Some(5));
+        assert_eq!(created.tags(), vec!["thrower", "duo with X"]);
+        assert_eq!(get_note(uuid.clone()).unwrap(), created);
+
+        let updated = save_note(PlayerNote { text: "actually fine".to_string(), rating: None, ..created.clone() }).unwrap();
+
+        assert_eq!(updated.rowid, created.rowid);
+        assert_eq!(updated.created_at, created.created_at);
+        assert_eq!(get_note(uuid.clone()).unwrap().text, "actually fine");
+        assert_eq!(select!(Vec<PlayerNote> "WHERE uuid =" uuid).unwrap().len(), 1);
+
+        delete_note(uuid.clone()).unwrap();
+        assert!(matches!(get_note(uuid.clone()), Err(DbError::NotFound)));
+        assert!(matches!(delete_note(uuid), Err(DbError::NotFound)));
+    }
+
+    #[test]
+    fn flags_players() {
+        let _db = use_test_db();
+
+        let uuid = "flagged-player".to_string();
+
+        set_flag(uuid.clone(), Some(PlayerFlag::Avoid), " threw last game ".to_string()).unwrap();
+
+        let user = get_user(uuid.clone()).unwrap();
+        assert_eq!(user.flag(), Some(PlayerFlag::Avoid));
+        assert_eq!(user.flag_reason, "threw last game");
+
+        // Flags survive the counts being recomputed
+        sync_user(uuid.clone()).unwrap();
+        assert_eq!(get_user(uuid.clone()).unwrap().flag(), Some(PlayerFlag::Avoid));
+
+        set_flag(uuid.clone(), None, "ignored".to_string()).unwrap();
+
+        let user = get_user(uuid).unwrap();
+        assert_eq!(user.flag(), None);
+        assert_eq!(user.flag_reason, "");
+    }
+
+    fn outcome(match_id: &str, winner: &str, players: &[(&str, &str)]) -> MatchOutcome {
+        MatchOutcome {
+            match_id: match_id.to_string(),
+            teams: ["Blue", "Red"].iter().map(|team| MatchResult {
+                team_id: team.to_string(),
+                won: *team == winner,
+                rounds_won: if *team == winner { 13 } else { 7 },
+                rounds_played: 20,
+                ..Default::default()
+            }).collect(),
+            players: players.iter().map(|(uuid, team)| MatchPlayerStats {
+                uuid: uuid.to_string(),
+                team_id: team.to_string(),
+                kills: 15,
+                deaths: 12,
+                assists: 4,
+                score: 4000,
+                ..Default::default()
+            }).collect(),
+        }
+    }
+
+    #[test]
+    fn tracks_wins_and_losses() {
+        let _db = use_test_db();
+
+        let (me, other) = ("results-me", "results-other");
+
+        for (i, (winner, other_team)) in [("Blue", "Blue"), ("Blue", "Blue"), ("Red", "Blue"), ("Blue", "Red")].into_iter().enumerate() {
+            let match_id = format!("results-{}", i);
+
+            add_match(Match { match_id: match_id.clone(), puuid: me.to_string(), start_time: 1000 + i as i64, ..Default::default() }).unwrap();
+            record_results(outcome(&match_id, winner, &[(me, "Blue"), (other, other_team)])).unwrap();
+        }
+
+        // A match without results yet is still pending
+        add_match(Match { match_id: "results-pending".to_string(), puuid: me.to_string(), start_time: 2000, ..Default::default() }).unwrap();
+        assert_eq!(pending_results(me, 0, 10).unwrap(), vec!["results-pending".to_string()]);
+        assert!(pending_results(me, 3000, 10).unwrap().is_empty());
+
+        // Recording the same match again changes nothing
+        record_results(outcome("results-0", "Red", &[(me, "Blue"), (other, "Blue")])).unwrap();
+        assert_eq!(select!(Vec<MatchResult> "WHERE match_id = 'results-0'").unwrap().len(), 2);
+
+        let stats = encounter_stats(other.to_string()).unwrap();
+        assert_eq!(stats.win_loss, WinLoss { with_wins: 2, with_losses: 1, against_wins: 1, against_losses: 0 });
+        assert_eq!(stats.win_loss.to_string(), "2–1 with, 1–0 against");
+
+        // Latest first: won against, lost with, then two wins
+        assert_eq!(stats.streak, 1);
+
+        assert!(encounter_stats(me.to_string()).unwrap().win_loss.is_empty());
+    }
+
+    #[test]
+    fn aggregates_encounters() {
+        let _db = use_test_db();
+
+        let (me, other) = ("encounter-me", "encounter-other");
+
+        let games = [
+            ("/Game/Maps/Ascent/Ascent", "jett", false, Some("Red")),
+            ("/Game/Maps/Ascent/Ascent", "jett", true, Some("Red")),
+            ("/Game/Maps/Bonsai/Bonsai", "sova", false, Some("Blue")),
+            ("/Game/Maps/Ascent/Ascent", "jett", false, Some("Blue")),
+            ("/Game/Maps/Bonsai/Bonsai", "jett", true, None),
+        ];
+
+        for (i, (map_id, agent_id, enemy, winner)) in games.into_iter().enumerate() {
+            let match_id = format!("encounter-{}", i);
+
+            record_match(MatchSnapshot {
+                details: Match { match_id: match_id.clone(), map_id: map_id.to_string(), start_time: 5000 + i as i64, puuid: me.to_string(), ..Default::default() },
+                participants: vec![
+                    Participant { uuid: me.to_string(), name: "me".to_string(), tag: "1".to_string(), ..Default::default() },
+                    Participant { uuid: other.to_string(), name: "other".to_string(), tag: "2".to_string(), agent_id: agent_id.to_string(), enemy },
+                ],
+            }).unwrap();
+
+            // The last match hasn't finished
+            if let Some(winner) = winner {
+                let other_team = if enemy { "Red" } else { "Blue" };
+                record_results(outcome(&match_id, winner, &[(me, "Blue"), (other, other_team)])).unwrap();
+            }
+        }
+
+        let stats = encounter_stats(other.to_string()).unwrap();
+
+        assert_eq!((stats.together, stats.against), (3, 2));
+        assert_eq!(stats.maps.iter().map(|x| (x.id.as_str(), x.matches)).collect::<Vec<_>>(), vec![("/Game/Maps/Ascent/Ascent", 3), ("/Game/Maps/Bonsai/Bonsai", 2)]);
+        assert_eq!(stats.maps[0], SideCounts { id: "/Game/Maps/Ascent/Ascent".to_string(), matches: 3, together: 2, against: 1 });
+        assert_eq!((stats.agents[0].id.as_str(), stats.agents[0].matches), ("jett", 4));
+        assert_eq!((stats.first_seen, stats.last_seen), (Some(5000), Some(5004)));
+
+        // Lost together, lost against them, then won together twice
+        assert_eq!(stats.win_loss, WinLoss { with_wins: 2, with_losses: 1, against_wins: 0, against_losses: 1 });
+        assert_eq!(stats.streak, 2);
+
+        assert_eq!(encounter_stats("encounter-nobody".to_string()).unwrap(), EncounterStats::default());
+    }
+
+    #[test]
+    fn only_reports_unique_violations_as_duplicates() {
+        let conn = turbosql::rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute("CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE)", []).unwrap();
+        conn.execute("INSERT INTO t (id, name) VALUES (1, 'a')", []).unwrap();
+
+        let insert = |sql: &str| DbError::from(turbosql::Error::Rusqlite(conn.execute(sql, []).unwrap_err()));
+
+        assert!(matches!(insert("INSERT INTO t (id, name) VALUES (2, 'a')"), DbError::Duplicate));
+        assert!(matches!(insert("INSERT INTO t (id, name) VALUES (1, 'b')"), DbError::Duplicate));
+        assert!(matches!(insert("INSERT INTO t (id, name) VALUES (3, NULL)"), DbError::Sqlite(_)));
+    }
+
+    #[test]
+    fn counts_sides() {
+        let log = |map_id: &str, agent_id: &str, enemy: Option<bool>| PlayerMatch {
+            participant: MatchHistory { agent_id: agent_id.to_string(), enemy, ..Default::default() },
+            details: Match { map_id: map_id.to_string(), ..Default::default() },
+        };
+
+        let history = [
+            log("Ascent", "jett", Some(false)),
+            log("Bind", "sova", Some(true)),
+            log("Ascent", "jett", Some(true)),
+            log("Ascent", "", None),
+        ];
+
+        assert_eq!(side_counts(&history, |x| &x.details.map_id), vec![
+            SideCounts { id: "Ascent".to_string(), matches: 3, together: 1, against: 1 },
+            SideCounts { id: "Bind".to_string(), matches: 1, together: 0, against: 1 },
+        ]);
+
+        let agents = side_counts(&history, |x| &x.participant.agent_id);
+        assert_eq!(agents.len(), 2);
+        assert_eq!(agents[0], SideCounts { id: "jett".to_string(), matches: 2, together: 1, against: 1 });
+    }
+
+    #[test]
+    fn lists_recent_players() {
+        let _db = use_test_db();
+
+        let (me, old, new) = ("recent-me", "recent-old", "recent-new");
+
+        // The last match is without me and shouldn't show up
+        for (i, other, name, tag) in [(0, old, "", ""), (1, new, "Recent", "NEW"), (2, "recent-stranger", "Stranger", "1")] {
+            let mut participants = vec![Participant { uuid: other.to_string(), name: name.to_string(), tag: tag.to_string(), ..Default::default() }];
+
+            if other != "recent-stranger" {
+                participants.push(Participant { uuid: me.to_string(), name: "me".to_string(), tag: "1".to_string(), ..Default::default() });
+            }
+
+            record_match(MatchSnapshot {
+                details: Match { match_id: format!("recent-{}", i), start_time: 5000 + i, ..Default::default() },
+                participants,
+            }).unwrap();
+        }
+
+        // An older name shouldn't replace the latest one
+        execute!("INSERT INTO namehistory (uuid, name, tag, name_time) VALUES (" new ", 'Renamed', 'OLD', 0)").unwrap();
+
+        let recent = recent_players(me, 2).unwrap();
+        assert_eq!(recent.iter().map(|x| x.uuid.as_str()).collect::<Vec<&str>>(), vec![new, old]);
+        assert_eq!((recent[0].name.as_str(), recent[0].tag.as_str()), ("Recent", "NEW"));
+        assert_eq!(recent[1].name, "");
+    }
+
+    #[test]
+    fn scores_search_matches() {
+        assert_eq!(search_score("jett diff", "Jett Diff", "0001"), Some(0));
+        assert_eq!(search_score("JETT", "jett diff", "0001"), Some(1));
+        assert_eq!(search_score("diff", "jett diff", "0001"), Some(2));
+        assert_eq!(search_score("jtdf", "jett diff", "0001"), Some(3));
+        assert_eq!(search_score("sova", "jett diff", "0001"), None);
+
+        assert_eq!(search_score("jett#00", "jett diff", "0001"), Some(1));
+        assert_eq!(search_score("jett#EU", "jett diff", "0001"), None);
+        assert_eq!(search_score("#000", "jett diff", "0001"), Some(1));
+
+        assert!(is_puuid("c7d8e9f0-1a2b-5c3d-8e4f-5a6b7c8d9e0f"));
+        assert!(!is_puuid("c7d8e9f0-1a2b-5c3d-8e4f-5a6b7c8d9e0"));
+        assert!(!is_puuid("jett diff"));
+    }
+
+    #[test]
+    fn searches_names_and_puuids() {
+        let _db = use_test_db();
+
+        let (renamed, other) = ("5ea4c8a1-0000-4000-8000-000000000001", "5ea4c8a1-0000-4000-8000-000000000002");
+
+        execute!("INSERT INTO namehistory (uuid, name, tag, name_time) VALUES (" renamed ", 'Searchold', 'EUW', 100)").unwrap();
+        execute!("INSERT INTO namehistory (uuid, name, tag, name_time) VALUES (" renamed ", 'Searchnew', 'EUW', 200)").unwrap();
+        execute!("INSERT INTO namehistory (uuid, name, tag, name_time) VALUES (" other ", 'Searchnewer', 'NA1', 200)").unwrap();
+        add_user(renamed).unwrap();
+        execute!("UPDATE userdatabase SET times_played = 4 WHERE uuid =" renamed).unwrap();
+
+        // Past names find the player under their latest name
+        let results = search_players("searchold", 10).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!((results[0].name.as_str(), results[0].matched.as_deref()), ("Searchnew", Some("Searchold#EUW")));
+        assert_eq!(results[0].times_played, 4);
+
+        // Exact matches first, then by times played
+        let results = search_players("SEARCHNEW", 10).unwrap();
+        assert_eq!(results.iter().map(|x| x.uuid.as_str()).collect::<Vec<&str>>(), vec![renamed, other]);
+        assert_eq!(results[0].matched, None);
+
+        assert_eq!(search_players("searchnew#na", 10).unwrap()[0].uuid, other);
+        assert_eq!(search_players(&other.to_uppercase(), 10).unwrap()[0].name, "Searchnewer");
+        assert!(search_players("5ea4c8a1-0000-4000-8000-000000000003", 10).unwrap().is_empty());
+        assert!(search_players("  ", 10).unwrap().is_empty());
+
+        assert_eq!(get_latest_name(renamed.to_string()).unwrap().unwrap().name, "Searchnew");
+        assert!(get_latest_name("5ea4c8a1-0000-4000-8000-000000000003".to_string()).unwrap().is_none());
+    }
+
+    #[test]
+    fn logs_matches_newest_first() {
+        let _db = use_test_db();
+
+        for (i, enemy) in [false, true].into_iter().enumerate() {
+            record_match(MatchSnapshot {
+                details: Match { match_id: format!("log-{}", i), map_id: "/Game/Maps/Ascent/Ascent".to_string(), start_time: 5000 + i as i64, ..Default::default() },
+                participants: vec![
+                    Participant { uuid: "log-me".to_string(), name: "me".to_string(), tag: "1".to_string(), ..Default::default() },
+                    Participant { uuid: "log-other".to_string(), name: "other".to_string(), tag: "2".to_string(), agent_id: "jett".to_string(), enemy },
+                ],
+            }).unwrap();
+        }
+
+        let log = match_log(0, 2).unwrap();
+        assert_eq!(log.iter().map(|x| x.details.match_id.as_str()).collect::<Vec<&str>>(), vec!["log-1", "log-0"]);
+
+        let other = log[0].participants.iter().find(|x| x.uuid == "log-other").unwrap();
+        assert_eq!(other, &LoggedParticipant {
+            uuid: "log-other".to_string(),
+            name: "other".to_string(),
+            tag: "2".to_string(),
+            agent_id: "jett".to_string(),
+            enemy: Some(true),
+        });
+        assert_eq!(log[0].participants.len(), 2);
+
+        let next = match_log(1, 1).unwrap();
+        assert_eq!(next[0].details.match_id, "log-0");
+        assert_eq!(next[0].participants.iter().find(|x| x.uuid == "log-other").unwrap().enemy, Some(false));
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+const LOCKFILE: [&str; 4] = ["Riot Games", "Riot Client", "Config", "lockfile"];
+const SHOOTER_GAME_LOG: [&str; 4] = ["VALORANT", "Saved", "Logs", "ShooterGame.log"];
+
+// Environment variables that point straight at a file
+pub const LOCKFILE_ENV: &str = "VALPLUS_LOCKFILE";
+pub const SHOOTER_GAME_LOG_ENV: &str = "VALPLUS_SHOOTER_GAME_LOG";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSource {
+    Env(&'static str),
+    Settings,
+    LocalAppData,
+    WinePrefix,
+    Lutris,
+    SteamCompat,
+}
+
+impl fmt::Display for PathSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathSource::Env(var) => write!(f, "${}", var),
+            PathSource::Settings => write!(f, "settings"),
+            PathSource::LocalAppData => write!(f, "%LOCALAPPDATA%"),
+            PathSource::WinePrefix => write!(f, "Wine prefix"),
+            PathSource::Lutris => write!(f, "Lutris"),
+            PathSource::SteamCompat => write!(f, "Steam/Proton"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Found {
+    pub path: PathBuf,
+    pub source: PathSource,
+}
+
+impl fmt::Display for Found {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.path.display(), self.source)
+    }
+}
+
+// Finds the Riot lockfile and ShooterGame.log, which live under the Windows "AppData/Local" folder.
+// On Linux that folder sits inside whichever Wine prefix the client was installed into.
+#[derive(Debug, Clone, Default)]
+pub struct Discovery {
+    // Files set through the environment, tried before any folder
+    lockfile: Option<PathBuf>,
+    shooter_game_log: Option<PathBuf>,
+    // "AppData/Local" folders in order of preference
+    app_data_dirs: Vec<(PathBuf, PathSource)>,
+}
+
+impl Discovery {
+    // Files named by the environment first, then the folder from settings and the default install locations
+    pub fn new(app_data: Option<PathBuf>) -> Self {
+        Self {
+            lockfile: std::env::var_os(LOCKFILE_ENV).map(PathBuf::from),
+            shooter_game_log: std::env::var_os(SHOOTER_GAME_LOG_ENV).map(PathBuf::from),
+            ..Self::from_dirs(app_data_dirs(app_data))
+        }
+    }
+
+    // Searches only these "AppData/Local" folders
+    pub fn from_dirs(app_data_dirs: Vec<(PathBuf, PathSource)>) -> Self {
+        Self { lockfile: None, shooter_game_log: None, app_data_dirs }
+    }
+
+    // Returns the first lockfile that exists, or every path that was tried
+    pub fn find_lockfile(&self) -> Result<Found, Vec<PathBuf>> {
+        Self::find(self.candidates(self.lockfile.as_ref(), LOCKFILE_ENV, &LOCKFILE))
+    }
+
+    pub fn find_shooter_game_log(&self) -> Result<Found, Vec<PathBuf>> {
+        Self::find(self.candidates(self.shooter_game_log.as_ref(), SHOOTER_GAME_LOG_ENV, &SHOOTER_GAME_LOG))
+    }
+
+    fn find(candidates: Vec<Found>) -> Result<Found, Vec<PathBuf>> {
+        let mut searched = Vec::new();
+
+        for candidate in candidates {
+            if candidate.path.is_file() {
+                return Ok(candidate)
+            }
+
+            searched.push(candidate.path);
+        }
+
+        Err(searched)
+    }
+
+    fn candidates(&self, env_path: Option<&PathBuf>, env_var: &'static str, relative: &[&str]) -> Vec<Found> {
+        let mut candidates = Vec::new();
+
+        if let Some(path) = env_path {
+            candidates.push(Found { path: path.clone(), source: PathSource::Env(env_var) });
+        }
+
+        for (dir, source) in &self.app_data_dirs {
+            let path = relative.iter().fold(dir.clone(), |path, part| path.join(part));
+            candidates.push(Found { path, source: source.clone() });
+        }
+
+        candidates
+    }
+}
+
+// Every "AppData/Local" directory worth checking, in order of preference
+fn app_data_dirs(app_data: Option<PathBuf>) -> Vec<(PathBuf, PathSource)> {
+    let mut dirs = Vec::new();
+
+    if let Some(app_data) = app_data {
+        dirs.push((app_data, PathSource::Settings));
+    }
+
+    if let Some(path) = std::env::var_os("LOCALAPPDATA") {
+        dirs.push((PathBuf::from(path), PathSource::LocalAppData));
+    }
+
+    let mut prefixes = Vec::new();
+
+    if let Some(path) = std::env::var_os("WINEPREFIX") {
+        prefixes.push((PathBuf::from(path), PathSource::Env("WINEPREFIX")));
+    }
+
+    if let Some(base_dirs) = directories_next::BaseDirs::new() {
+        let home = base_dirs.home_dir();
+
+        prefixes.push((home.join(".wine"), PathSource::WinePrefix));
+
+        // Lutris installs each game into its own prefix under ~/Games
+        for prefix in sub_dirs(&home.join("Games")) {
+            prefixes.push((prefix, PathSource::Lutris));
+        }
+
+        for steam in [home.join(".steam").join("steam"), home.join(".local").join("share").join("Steam")] {
+            for compat in sub_dirs(&steam.join("steamapps").join("compatdata")) {
+                prefixes.push((compat.join("pfx"), PathSource::SteamCompat));
+            }
+        }
+    }
+
+    for (prefix, source) in prefixes {
+        for user in sub_dirs(&prefix.join("drive_c").join("users")) {
+            dirs.push((user.join("AppData").join("Local"), source.clone()));
+        }
+    }
+
+    dirs
+}
+
+fn sub_dirs(path: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return Vec::new()
+    };
+
+    let mut dirs: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect();
+
+    // read_dir order is platform dependent, keep the search order stable
+    dirs.sort();
+    dirs
+}
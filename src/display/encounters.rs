@@ -0,0 +1,63 @@
+use std::time::Duration;
+use eframe::egui;
+use eframe::egui::Ui;
+use crate::database::{EncounterStats, SideCounts};
+use crate::images::ImageData;
+
+fn row(ui: &mut Ui, label: &str, value: String) {
+    ui.horizontal(|ui| {
+        ui.add_space(10.0);
+        ui.label(egui::RichText::new(label).strong());
+        ui.label(value);
+    });
+}
+
+// "Won 3 in a row", empty without finished matches
+pub fn streak_text(streak: i64) -> String {
+    match streak {
+        0 => String::new(),
+        streak if streak > 0 => format!("Won {} in a row", streak),
+        streak => format!("Lost {} in a row", -streak),
+    }
+}
+
+// "Ascent (3), Bonsai (2)" with ids named through the loaded images where possible
+pub fn top_list(counts: &[SideCounts], name: impl Fn(&str) -> Option<String>) -> String {
+    counts.iter()
+        .take(3)
+        .map(|x| format!("{} ({})", name(&x.id).unwrap_or_else(|| x.id.clone()), x.matches))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+// Rows for the expanded player panel, `played` counts matches the player was recorded in
+pub fn show_encounters(ui: &mut Ui, stats: &EncounterStats, played: usize, images: Option<&ImageData>, time_now: u64) {
+    let formatter = timeago::Formatter::new();
+    let ago = |time: i64| formatter.convert(Duration::from_secs((time_now as i64 - time).max(0) as u64));
+
+    if let Some(first_seen) = stats.first_seen {
+        row(ui, "First Played:", ago(first_seen));
+    }
+
+    if let Some(last_seen) = stats.last_seen {
+        row(ui, "Last Played:", ago(last_seen));
+    }
+
+    row(ui, "Played:", format!("{} times ({} with, {} against)", played, stats.together, stats.against));
+
+    if !stats.win_loss.is_empty() {
+        row(ui, "Record:", stats.win_loss.to_string());
+    }
+
+    if stats.streak != 0 {
+        row(ui, "Streak:", streak_text(stats.streak));
+    }
+
+    if !stats.maps.is_empty() {
+        row(ui, "Maps:", top_list(&stats.maps, |id| images.and_then(|x| x.find_map(id)).map(|x| x.name.clone())));
+    }
+
+    if !stats.agents.is_empty() {
+        row(ui, "Agents:", top_list(&stats.agents, |id| images.and_then(|x| x.find_agent(id)).map(|x| x.name.clone())));
+    }
+}
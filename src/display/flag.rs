@@ -0,0 +1,153 @@
+use eframe::egui;
+use eframe::egui::{Color32, Ui};
+use crate::{LoadedPlayer, TeamType};
+use crate::database::PlayerFlag;
+
+// A flag being edited inline, nothing is written until it's saved
+#[derive(Debug, Clone, Default)]
+pub struct FlagEdit {
+    pub uuid: String,
+    pub flag: Option<PlayerFlag>,
+    pub reason: String,
+}
+
+pub enum FlagAction {
+    None,
+    Save,
+    Cancel,
+}
+
+impl FlagEdit {
+    pub fn new(player: &LoadedPlayer) -> Self {
+        Self {
+            uuid: player.uuid.clone(),
+            flag: player.flag,
+            reason: player.flag_reason.clone(),
+        }
+    }
+}
+
+pub fn flag_color(flag: PlayerFlag) -> Color32 {
+    match flag {
+        PlayerFlag::Avoid => Color32::from_rgb(200, 60, 60),
+        PlayerFlag::FriendOfFriend => Color32::from_rgb(60, 130, 200),
+        PlayerFlag::Watch => Color32::from_rgb(220, 150, 40),
+    }
+}
+
+// "AVOID: name#tag is on the enemy team — reason"
+pub fn alert_text(player: &LoadedPlayer) -> Option<String> {
+    let flag = player.flag?;
+
+    let name = if player.incognito {
+        "An incognito player".to_string()
+    } else {
+        format!("{}#{}", player.name, player.tag)
+    };
+
+    let team = match player.team {
+        TeamType::Ally => "your team",
+        TeamType::Enemy => "the enemy team",
+    };
+
+    let mut text = format!("{}: {} is on {}", flag.to_string().to_uppercase(), name, team);
+
+    if !player.flag_reason.is_empty() {
+        text.push_str(&format!(" — {}", player.flag_reason));
+    }
+
+    Some(text)
+}
+
+// One banner per flagged player in the match
+pub fn show_alerts<'a>(ui: &mut Ui, players: impl Iterator<Item = &'a LoadedPlayer>) {
+    for player in players {
+        let (Some(flag), Some(text)) = (player.flag, alert_text(player)) else {
+            continue
+        };
+
+        egui::Frame::none()
+            .fill(flag_color(flag))
+            .rounding(10.0)
+            .inner_margin(10.0)
+            .show(ui, |ui| {
+                ui.set_width(ui.available_width());
+                ui.label(egui::RichText::new(format!("⚠ {}", text)).color(Color32::WHITE).strong());
+            });
+
+        ui.add_space(5.0);
+    }
+}
+
+pub fn show_flag(ui: &mut Ui, player: &LoadedPlayer) {
+    let Some(flag) = player.flag else {
+        return
+    };
+
+    ui.horizontal_wrapped(|ui| {
+        ui.colored_label(flag_color(flag), flag.to_string());
+
+        if !player.flag_reason.is_empty() {
+            ui.label(&player.flag_reason);
+        }
+    });
+}
+
+pub fn edit_flag(ui: &mut Ui, edit: &mut FlagEdit) -> FlagAction {
+    let mut action = FlagAction::None;
+
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_source(format!("flag_{}", edit.uuid))
+            .selected_text(edit.flag.map(|x| x.to_string()).unwrap_or_else(|| "None".to_string()))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut edit.flag, None, "None");
+
+                for flag in PlayerFlag::ALL {
+                    ui.selectable_value(&mut edit.flag, Some(flag), flag.to_string());
+                }
+            });
+
+        ui.add_enabled(edit.flag.is_some(), egui::TextEdit::singleline(&mut edit.reason).hint_text("Reason"));
+    });
+
+    ui.horizontal(|ui| {
+        if ui.button("Save").clicked() {
+            action = FlagAction::Save;
+        }
+
+        if ui.button("Cancel").clicked() {
+            action = FlagAction::Cancel;
+        }
+    });
+
+    action
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alert_names_team_and_reason() {
+        let mut player = LoadedPlayer {
+            name: "jett diff".to_string(),
+            tag: "0001".to_string(),
+            team: TeamType::Enemy,
+            flag: Some(PlayerFlag::Avoid),
+            flag_reason: "Throws on attack".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(alert_text(&player).unwrap(), "AVOID: jett diff#0001 is on the enemy team — Throws on attack");
+
+        player.team = TeamType::Ally;
+        player.flag = Some(PlayerFlag::FriendOfFriend);
+        player.flag_reason.clear();
+        player.incognito = true;
+
+        assert_eq!(alert_text(&player).unwrap(), "FRIEND OF FRIEND: An incognito player is on your team");
+
+        player.flag = None;
+        assert!(alert_text(&player).is_none());
+    }
+}
@@ -0,0 +1,137 @@
+use eframe::egui;
+use eframe::egui::Ui;
+use serde::{Deserialize, Serialize};
+use crate::{LoadedPlayer, Settings, TeamType};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum TeamFilter {
+    #[default]
+    All,
+    Allies,
+    Enemies,
+}
+
+impl TeamFilter {
+    pub const ALL: [TeamFilter; 3] = [TeamFilter::All, TeamFilter::Allies, TeamFilter::Enemies];
+
+    fn label(&self) -> &'static str {
+        match self {
+            TeamFilter::All => "Both teams",
+            TeamFilter::Allies => "Allies",
+            TeamFilter::Enemies => "Enemies",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum PlayerSort {
+    // Allies first, otherwise in match order
+    #[default]
+    Team,
+    LastPlayed,
+    TimesPlayed,
+}
+
+impl PlayerSort {
+    pub const ALL: [PlayerSort; 3] = [PlayerSort::Team, PlayerSort::LastPlayed, PlayerSort::TimesPlayed];
+
+    fn label(&self) -> &'static str {
+        match self {
+            PlayerSort::Team => "Team",
+            PlayerSort::LastPlayed => "Last played",
+            PlayerSort::TimesPlayed => "Times played",
+        }
+    }
+}
+
+// The players on the home page after the threshold, filters and sort in settings
+pub fn visible_players<'a>(players: &'a [LoadedPlayer], settings: &Settings) -> Vec<&'a LoadedPlayer> {
+    let mut visible: Vec<&LoadedPlayer> = players.iter()
+        .filter(|x| settings.show_all || x.times_played >= settings.min_times_played)
+        .filter(|x| match settings.team_filter {
+            TeamFilter::All => true,
+            TeamFilter::Allies => matches!(x.team, TeamType::Ally),
+            TeamFilter::Enemies => matches!(x.team, TeamType::Enemy),
+        })
+        .filter(|x| !settings.hide_incognito || !x.incognito)
+        .collect();
+
+    match settings.sort {
+        PlayerSort::Team => visible.sort_by_key(|x| matches!(x.team, TeamType::Enemy)),
+        PlayerSort::LastPlayed => visible.sort_by_key(|x| std::cmp::Reverse(x.last_played)),
+        PlayerSort::TimesPlayed => visible.sort_by_key(|x| std::cmp::Reverse(x.times_played)),
+    }
+
+    visible
+}
+
+pub fn show_filters(ui: &mut Ui, settings: &mut Settings) {
+    ui.horizontal_wrapped(|ui| {
+        ui.checkbox(&mut settings.show_all, "Show all")
+            .on_hover_text(format!("Include players met fewer than {} times", settings.min_times_played));
+
+        egui::ComboBox::from_id_source("team_filter")
+            .selected_text(settings.team_filter.label())
+            .show_ui(ui, |ui| {
+                for filter in TeamFilter::ALL {
+                    ui.selectable_value(&mut settings.team_filter, filter, filter.label());
+                }
+            });
+
+        ui.checkbox(&mut settings.hide_incognito, "Hide incognito");
+
+        egui::ComboBox::from_id_source("player_sort")
+            .selected_text(format!("Sort: {}", settings.sort.label()))
+            .show_ui(ui, |ui| {
+                for sort in PlayerSort::ALL {
+                    ui.selectable_value(&mut settings.sort, sort, sort.label());
+                }
+            });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(uuid: &str, team: TeamType, times_played: i64, last_played: i64, incognito: bool) -> LoadedPlayer {
+        LoadedPlayer { uuid: uuid.to_string(), team, times_played, last_played, incognito, ..Default::default() }
+    }
+
+    fn uuids(players: Vec<&LoadedPlayer>) -> Vec<&str> {
+        players.into_iter().map(|x| x.uuid.as_str()).collect()
+    }
+
+    #[test]
+    fn filters_and_sorts_players() {
+        let players = [
+            player("enemy", TeamType::Enemy, 5, 300, false),
+            player("new", TeamType::Ally, 0, 0, false),
+            player("ally", TeamType::Ally, 3, 100, false),
+            player("incognito", TeamType::Enemy, 4, 200, true),
+        ];
+
+        let mut settings = Settings::default();
+        assert_eq!(uuids(visible_players(&players, &settings)), vec!["ally", "enemy", "incognito"]);
+
+        settings.show_all = true;
+        assert_eq!(uuids(visible_players(&players, &settings)), vec!["new", "ally", "enemy", "incognito"]);
+
+        settings.show_all = false;
+        settings.min_times_played = 4;
+        assert_eq!(uuids(visible_players(&players, &settings)), vec!["enemy", "incognito"]);
+
+        settings.min_times_played = 0;
+        settings.team_filter = TeamFilter::Enemies;
+        settings.hide_incognito = true;
+        assert_eq!(uuids(visible_players(&players, &settings)), vec!["enemy"]);
+
+        settings.team_filter = TeamFilter::All;
+        settings.hide_incognito = false;
+        settings.sort = PlayerSort::LastPlayed;
+        assert_eq!(uuids(visible_players(&players, &settings)), vec!["enemy", "incognito", "ally", "new"]);
+
+        settings.sort = PlayerSort::TimesPlayed;
+        assert_eq!(uuids(visible_players(&players, &settings)), vec!["enemy", "incognito", "ally", "new"]);
+    }
+}
@@ -0,0 +1,183 @@
+use std::time::Duration;
+use eframe::egui;
+use eframe::egui::{Color32, Ui, Vec2};
+use poll_promise::Promise;
+use crate::MyApp;
+use crate::database;
+use crate::database::{DbError, LoggedMatch, LoggedParticipant, Match};
+
+const MATCHES_PER_PAGE: i64 = 20;
+
+// The personal match log on Page::Matches
+#[derive(Default)]
+pub struct MatchLog {
+    page: i64,
+    // None until the page is loaded
+    pub matches: Option<Vec<LoggedMatch>>,
+    promise: Option<Promise<Result<Vec<LoggedMatch>, DbError>>>,
+}
+
+impl MatchLog {
+    fn load(&mut self) {
+        let (offset, limit) = (self.page * MATCHES_PER_PAGE, MATCHES_PER_PAGE);
+
+        self.promise = Some(Promise::spawn_thread("match_log", move || database::match_log(offset, limit)));
+    }
+
+    fn poll(&mut self) {
+        if let Some(promise) = self.promise.take() {
+            match promise.try_take() {
+                Ok(matches) => {
+                    self.matches = Some(matches.unwrap_or_else(|err| {
+                        println!("Couldnt get match log: {}", err);
+                        Vec::new()
+                    }));
+                }
+                Err(promise) => self.promise = Some(promise),
+            }
+        }
+    }
+}
+
+// "Competitive" from the queue, or the game mode for matches recorded without one
+pub fn mode_name(details: &Match) -> String {
+    if !details.queue_id.is_empty() {
+        let mut queue = details.queue_id.chars();
+        return queue.next().map(|x| x.to_uppercase().chain(queue).collect()).unwrap_or_default()
+    }
+
+    // "/Game/GameModes/Bomb/BombGameMode.BombGameMode_C"
+    let mode = details.gamemode_id.trim_start_matches("/Game/GameModes/").split('/').next().unwrap_or_default();
+
+    match mode {
+        "Bomb" => "Standard".to_string(),
+        "QuickBomb" => "Spike Rush".to_string(),
+        "GunGame" => "Escalation".to_string(),
+        "OneForAll" => "Replication".to_string(),
+        "HURM" => "Team Deathmatch".to_string(),
+        mode => mode.to_string(),
+    }
+}
+
+fn show_side(ui: &mut Ui, title: &str, color: Color32, participants: &[&LoggedParticipant], app: &MyApp, open: &mut Option<String>) {
+    ui.colored_label(color, title);
+
+    for participant in participants {
+        let name = if participant.name.is_empty() { participant.uuid.clone() } else { format!("{}#{}", participant.name, participant.tag) };
+
+        ui.horizontal(|ui| {
+            if let Some(agent) = app.images.as_ref().and_then(|x| x.find_agent(&participant.agent_id)) {
+                ui.add(egui::Image::new(agent.icon.clone()).fit_to_exact_size(Vec2::new(20.0, 20.0)));
+            }
+
+            if ui.link(name).clicked() {
+                *open = Some(participant.uuid.clone());
+            }
+        });
+    }
+}
+
+pub fn show_matches(app: &mut MyApp, ui: &mut Ui) {
+    app.match_log.poll();
+
+    if app.match_log.matches.is_none() && app.match_log.promise.is_none() {
+        app.match_log.load();
+    }
+
+    let time_now = app.settings.time_now();
+    let formatter = timeago::Formatter::new();
+    let mut open = None;
+    let mut page = app.match_log.page;
+
+    let matches = app.match_log.matches.as_deref().unwrap_or_default();
+
+    ui.horizontal(|ui| {
+        if ui.add_enabled(page > 0, egui::Button::new("<")).clicked() {
+            page -= 1;
+        }
+
+        ui.label(format!("Page {}", page + 1));
+
+        // A full page means there may be more
+        if ui.add_enabled(matches.len() as i64 == MATCHES_PER_PAGE, egui::Button::new(">")).clicked() {
+            page += 1;
+        }
+    });
+
+    ui.vertical(|ui| ui.add(egui::widgets::Separator::default().spacing(10.0)));
+
+    if app.match_log.promise.is_some() {
+        ui.label("Loading...");
+        ui.ctx().request_repaint();
+    }
+
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        if matches.is_empty() && app.match_log.promise.is_none() {
+            ui.colored_label(Color32::GRAY, "No matches recorded yet");
+        }
+
+        for logged in matches {
+            let map = app.images.as_ref().and_then(|x| x.find_map(&logged.details.map_id));
+
+            egui::Frame::none()
+                .fill(Color32::from_rgb(31, 31, 31))
+                .rounding(10.0)
+                .inner_margin(10.0)
+                .show(ui, |ui| {
+                    ui.set_width(ui.available_width());
+
+                    ui.horizontal(|ui| {
+                        // Map Splash
+                        if let Some(map) = map {
+                            ui.add(
+                                egui::Image::new(map.icon.clone())
+                                    .fit_to_exact_size(Vec2::new(160.0, 90.0))
+                                    .maintain_aspect_ratio(false)
+                                    .rounding(10.0)
+                            );
+                        }
+
+                        ui.vertical(|ui| {
+                            ui.colored_label(Color32::WHITE, map.map_or(logged.details.map_id.clone(), |x| x.name.clone()));
+                            ui.label(mode_name(&logged.details));
+                            ui.colored_label(Color32::GRAY, formatter.convert(Duration::from_secs((time_now as i64 - logged.details.start_time).max(0) as u64)));
+                        });
+                    });
+
+                    let (enemies, allies): (Vec<&LoggedParticipant>, Vec<&LoggedParticipant>) = logged.participants.iter().partition(|x| x.enemy == Some(true));
+
+                    ui.columns(2, |columns| {
+                        show_side(&mut columns[0], "Team", Color32::GREEN, &allies, app, &mut open);
+                        show_side(&mut columns[1], "Enemy", Color32::RED, &enemies, app, &mut open);
+                    });
+                });
+
+            ui.add_space(5.0);
+        }
+    });
+
+    if page != app.match_log.page {
+        app.match_log.page = page;
+        app.match_log.matches = None;
+        app.match_log.load();
+    }
+
+    if let Some(uuid) = open {
+        app.open_profile(uuid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_modes() {
+        let details = |queue_id: &str, gamemode_id: &str| Match { queue_id: queue_id.to_string(), gamemode_id: gamemode_id.to_string(), ..Default::default() };
+
+        assert_eq!(mode_name(&details("competitive", "/Game/GameModes/Bomb/BombGameMode.BombGameMode_C")), "Competitive");
+        assert_eq!(mode_name(&details("", "/Game/GameModes/Bomb/BombGameMode.BombGameMode_C")), "Standard");
+        assert_eq!(mode_name(&details("", "/Game/GameModes/Deathmatch/DeathmatchGameMode.DeathmatchGameMode_C")), "Deathmatch");
+        assert_eq!(mode_name(&details("", "")), "");
+    }
+}
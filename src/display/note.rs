@@ -0,0 +1,128 @@
+use eframe::egui;
+use eframe::egui::{Color32, Ui};
+use crate::database::PlayerNote;
+
+// A note being edited inline, nothing is written until it's saved
+#[derive(Debug, Clone, Default)]
+pub struct NoteEdit {
+    pub uuid: String,
+    pub text: String,
+    pub tags: String,
+    pub rating: Option<i64>,
+}
+
+pub enum NoteAction {
+    None,
+    Save,
+    Delete,
+    Cancel,
+}
+
+impl NoteEdit {
+    pub fn new(uuid: &str, note: Option<&PlayerNote>) -> Self {
+        match note {
+            Some(note) => Self {
+                uuid: uuid.to_string(),
+                text: note.text.clone(),
+                tags: note.tags.clone(),
+                rating: note.rating,
+            },
+            None => Self {
+                uuid: uuid.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn to_note(&self) -> PlayerNote {
+        PlayerNote {
+            uuid: self.uuid.clone(),
+            text: self.text.trim().to_string(),
+            tags: self.tags.clone(),
+            rating: self.rating,
+            ..Default::default()
+        }
+    }
+}
+
+fn stars(rating: i64) -> String {
+    (1..=5).map(|i| if i <= rating { '★' } else { '☆' }).collect()
+}
+
+// Rating and tags on one line for the player card
+pub fn note_summary(note: &PlayerNote) -> String {
+    let mut parts = Vec::new();
+
+    if let Some(rating) = note.rating {
+        parts.push(stars(rating));
+    }
+
+    parts.extend(note.tags().iter().map(|tag| tag.to_string()));
+
+    if parts.is_empty() {
+        return note.text.lines().next().unwrap_or_default().to_string()
+    }
+
+    parts.join(" · ")
+}
+
+pub fn show_note(ui: &mut Ui, note: &PlayerNote) {
+    if let Some(rating) = note.rating {
+        ui.colored_label(Color32::GOLD, stars(rating));
+    }
+
+    let tags = note.tags();
+
+    if !tags.is_empty() {
+        ui.horizontal_wrapped(|ui| {
+            for tag in tags {
+                ui.label(egui::RichText::new(tag).background_color(Color32::from_rgb(51, 51, 61)));
+            }
+        });
+    }
+
+    if !note.text.is_empty() {
+        ui.label(&note.text);
+    }
+}
+
+pub fn edit_note(ui: &mut Ui, edit: &mut NoteEdit) -> NoteAction {
+    let mut action = NoteAction::None;
+
+    ui.horizontal(|ui| {
+        ui.label("Rating:");
+
+        for rating in 1..=5 {
+            let selected = edit.rating.is_some_and(|x| x >= rating);
+            let star = if selected { "★" } else { "☆" };
+
+            if ui.add(egui::Button::new(egui::RichText::new(star).color(Color32::GOLD)).frame(false)).clicked() {
+                // Clicking the current rating again clears it
+                edit.rating = if edit.rating == Some(rating) { None } else { Some(rating) };
+            }
+        }
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Tags:");
+        ui.add(egui::TextEdit::singleline(&mut edit.tags).hint_text("thrower, good duelist, duo with X"));
+    });
+
+    ui.add(egui::TextEdit::multiline(&mut edit.text).hint_text("Note").desired_rows(2).desired_width(f32::INFINITY));
+
+    ui.horizontal(|ui| {
+        if ui.button("Save").clicked() {
+            action = NoteAction::Save;
+        }
+
+        if ui.button("Cancel").clicked() {
+            action = NoteAction::Cancel;
+        }
+
+        if ui.button("Delete").clicked() {
+            action = NoteAction::Delete;
+        }
+    });
+
+    action
+}
@@ -0,0 +1,225 @@
+use std::time::Duration;
+use eframe::egui;
+use eframe::egui::{Color32, Ui, Vec2};
+use crate::LoadedPlayer;
+use crate::database::PlayerMatch;
+use crate::display::encounters;
+use crate::display::flag::{self, FlagAction, FlagEdit};
+use crate::display::note::{self, NoteAction, NoteEdit};
+use crate::images::ImageData;
+
+// What a player panel asked for this frame, applied by MyApp::apply_panel
+pub struct PanelResponse {
+    pub note: NoteAction,
+    pub flag: FlagAction,
+    // Puuid of the player whose profile should open
+    pub open_profile: Option<String>,
+}
+
+impl Default for PanelResponse {
+    fn default() -> Self {
+        Self {
+            note: NoteAction::None,
+            flag: FlagAction::None,
+            open_profile: None,
+        }
+    }
+}
+
+// Note and flag of a player with their inline editors.
+// The edits live in MyApp so they survive between frames
+pub fn show_note_and_flag(
+    ui: &mut Ui,
+    player: &LoadedPlayer,
+    note_edit: &mut Option<NoteEdit>,
+    flag_edit: &mut Option<FlagEdit>,
+) -> (NoteAction, FlagAction) {
+    let mut note_action = NoteAction::None;
+    let mut flag_action = FlagAction::None;
+
+    egui::Frame::none()
+        .fill(Color32::from_rgb(41, 41, 41))
+        .rounding(10.0)
+        .inner_margin(10.0)
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            ui.label(egui::RichText::new("Note:").strong());
+
+            match note_edit {
+                Some(edit) if edit.uuid == player.uuid => {
+                    note_action = note::edit_note(ui, edit);
+                }
+                _ => {
+                    if let Some(note) = &player.note {
+                        note::show_note(ui, note);
+                    }
+
+                    if ui.button(if player.note.is_some() { "Edit note" } else { "Add note" }).clicked() {
+                        *note_edit = Some(NoteEdit::new(&player.uuid, player.note.as_ref()));
+                    }
+                }
+            }
+
+            ui.add_space(5.0);
+            ui.label(egui::RichText::new("Flag:").strong());
+
+            match flag_edit {
+                Some(edit) if edit.uuid == player.uuid => {
+                    flag_action = flag::edit_flag(ui, edit);
+                }
+                _ => {
+                    flag::show_flag(ui, player);
+
+                    if ui.button(if player.flag.is_some() { "Edit flag" } else { "Flag player" }).clicked() {
+                        *flag_edit = Some(FlagEdit::new(player));
+                    }
+                }
+            }
+        });
+
+    (note_action, flag_action)
+}
+
+// One recorded match of a player with their agent, the map and which side they were on
+pub fn show_match_row(ui: &mut Ui, log: &PlayerMatch, images: Option<&ImageData>, time_now: u64) {
+    let formatter = timeago::Formatter::new();
+
+    let mut agent_image = String::new();
+    let (mut map_image, mut map_name) = (String::new(), String::new());
+
+    if let Some(images) = images {
+        if let Some(agent) = images.find_agent(&log.participant.agent_id) {
+            agent_image = agent.icon.clone();
+        }
+
+        if let Some(map) = images.find_map(&log.details.map_id) {
+            map_image = map.icon.clone();
+            map_name = map.name.clone();
+        }
+    }
+
+    let frame_color = if log.participant.enemy == Some(true) { Color32::from_rgb(41, 31, 41) } else { Color32::from_rgb(31, 41, 41) };
+
+    egui::Frame::none()
+        .fill(frame_color)
+        .rounding(10.0)
+        .show(ui, |ui| {
+            ui.set_max_width(ui.available_width());
+            ui.set_max_height(80.0);
+
+            ui.horizontal(|ui| {
+                // Agent Icon
+                ui.add(
+                    egui::Image::new(agent_image)
+                        .fit_to_exact_size(Vec2::new(80.0, 80.0))
+                        .maintain_aspect_ratio(false)
+                        .rounding(10.0)
+                );
+
+                // Data
+                ui.vertical(|ui| {
+                    ui.add_space(15.0);
+                    ui.colored_label(Color32::WHITE, map_name);
+
+                    if log.participant.enemy == Some(true) {
+                        ui.colored_label(Color32::RED, "Enemy");
+                    } else {
+                        ui.colored_label(Color32::GREEN, "Team");
+                    }
+
+                    ui.colored_label(Color32::WHITE, formatter.convert(Duration::from_secs((time_now as i64 - log.details.start_time).max(0) as u64)));
+                });
+
+                ui.add_space(ui.available_width() - 80.0);
+
+                // Map Icon
+                ui.add(
+                    egui::Image::new(map_image)
+                        .fit_to_exact_size(Vec2::new(80.0, 80.0))
+                        .maintain_aspect_ratio(false)
+                        .rounding(10.0)
+                );
+            });
+        });
+}
+
+// Note, flag, old names, encounters and the last matches of a player, shown under their expanded card
+pub fn show_player_panel(
+    ui: &mut Ui,
+    player: &LoadedPlayer,
+    note_edit: &mut Option<NoteEdit>,
+    flag_edit: &mut Option<FlagEdit>,
+    images: Option<&ImageData>,
+    time_now: u64,
+) -> PanelResponse {
+    let mut response = PanelResponse::default();
+
+    let formatter = timeago::Formatter::new();
+
+    // Note
+    ui.vertical(|ui| ui.add(egui::widgets::Separator::default().spacing(10.0)));
+
+    // A profile would show who an incognito player is
+    if !player.incognito && ui.button("View profile").clicked() {
+        response.open_profile = Some(player.uuid.clone());
+    }
+
+    (response.note, response.flag) = show_note_and_flag(ui, player, note_edit, flag_edit);
+
+    // Match History
+    if !player.match_history.is_empty() {
+        ui.vertical(|ui| ui.add(egui::widgets::Separator::default().spacing(10.0)));
+
+        egui::Frame::none()
+            .fill(Color32::from_rgb(41, 41, 41))
+            .rounding(10.0)
+            .show(ui, |ui| {
+                ui.set_width(ui.available_width());
+                ui.set_height(50.0);
+
+                ui.vertical(|ui| {
+                    ui.add_space(5.0);
+
+                    if !player.incognito {
+                        // Name History
+
+                        if !player.name_history.is_empty() {
+
+                            ui.horizontal(|ui| {
+                                ui.add_space(10.0);
+                                ui.label(egui::RichText::new("Old Usernames:").strong());
+                            });
+
+                            for name_history in &player.name_history {
+
+                                ui.horizontal(|ui| {
+                                    ui.add_space(10.0);
+                                    ui.label(
+                                        format!("{}#{} ({})",
+                                                &name_history.name,
+                                                &name_history.tag,
+                                                formatter.convert(Duration::from_secs((time_now as i64 - name_history.name_time.unwrap_or(time_now as i64)).max(0) as u64)),
+                                        )
+                                    );
+                                });
+                            }
+                        }
+
+                        ui.vertical(|ui| ui.add(egui::widgets::Separator::default().spacing(10.0)));
+                    }
+
+                    encounters::show_encounters(ui, &player.encounters, player.match_history.len(), images, time_now);
+
+                    ui.add_space(5.0);
+                });
+            });
+
+        ui.vertical(|ui| ui.add(egui::widgets::Separator::default().spacing(10.0)));
+
+        for log in player.match_history.iter().rev().take(10) {
+            show_match_row(ui, log, images, time_now);
+        }
+    }
+
+    response
+}
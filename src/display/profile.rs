@@ -0,0 +1,157 @@
+use std::time::Duration;
+use eframe::egui;
+use eframe::egui::{Color32, Ui};
+use crate::{LoadedPlayer, MyApp, Page};
+use crate::database::SideCounts;
+use crate::display::{encounters, flag, player};
+use crate::display::player::PanelResponse;
+
+const MATCHES_PER_PAGE: usize = 20;
+
+// The player shown on Page::Player, loaded by MyApp::open_profile
+pub struct Profile {
+    pub player: LoadedPlayer,
+    // Match history page, 0 is the newest matches
+    page: usize,
+    // Where the back button goes
+    pub back: Page,
+}
+
+impl Profile {
+    pub fn new(player: LoadedPlayer, back: Page) -> Self {
+        Self { player, page: 0, back }
+    }
+
+    fn page_count(&self) -> usize {
+        self.player.match_history.len().div_ceil(MATCHES_PER_PAGE).max(1)
+    }
+}
+
+fn show_breakdown(ui: &mut Ui, id: &str, title: &str, rows: &[SideCounts], name: impl Fn(&str) -> Option<String>) {
+    ui.label(egui::RichText::new(title).strong());
+
+    egui::Grid::new(id).num_columns(4).striped(true).show(ui, |ui| {
+        ui.label("");
+        ui.label("Played");
+        ui.label("With");
+        ui.label("Against");
+        ui.end_row();
+
+        for row in rows {
+            ui.label(name(&row.id).unwrap_or_else(|| row.id.clone()));
+            ui.label(row.matches.to_string());
+            ui.colored_label(Color32::GREEN, row.together.to_string());
+            ui.colored_label(Color32::RED, row.against.to_string());
+            ui.end_row();
+        }
+    });
+}
+
+pub fn show_profile(app: &mut MyApp, ui: &mut Ui) {
+    if let Some(promise) = app.profile_promise.take() {
+        match promise.try_take() {
+            Ok(profile) => app.profile = Some(profile),
+            Err(promise) => {
+                app.profile_promise = Some(promise);
+
+                ui.label("Loading...");
+                ui.ctx().request_repaint();
+                return
+            }
+        }
+    }
+
+    let Some(profile) = &mut app.profile else {
+        app.page = Page::Home;
+        return
+    };
+
+    let time_now = app.settings.time_now();
+    let formatter = timeago::Formatter::new();
+    let images = app.images.as_ref();
+    let mut response = PanelResponse::default();
+
+    let mut back = false;
+
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        let player = &profile.player;
+
+        ui.horizontal(|ui| {
+            if ui.button("Back").clicked() {
+                back = true;
+            }
+
+            let name = if player.name.is_empty() { player.uuid.clone() } else { format!("{}#{}", player.name, player.tag) };
+            ui.heading(name);
+
+            if let Some(player_flag) = player.flag {
+                ui.colored_label(flag::flag_color(player_flag), player_flag.to_string());
+            }
+        });
+
+        ui.vertical(|ui| ui.add(egui::widgets::Separator::default().spacing(10.0)));
+
+        (response.note, response.flag) = player::show_note_and_flag(ui, player, &mut app.note_edit, &mut app.flag_edit);
+
+        ui.vertical(|ui| ui.add(egui::widgets::Separator::default().spacing(10.0)));
+
+        encounters::show_encounters(ui, &player.encounters, player.match_history.len(), images, time_now);
+
+        ui.vertical(|ui| ui.add(egui::widgets::Separator::default().spacing(10.0)));
+
+        ui.columns(2, |columns| {
+            show_breakdown(&mut columns[0], "profile_maps", "Maps", &player.encounters.maps, |id| {
+                images.and_then(|x| x.find_map(id)).map(|x| x.name.clone())
+            });
+
+            show_breakdown(&mut columns[1], "profile_agents", "Agents", &player.encounters.agents, |id| {
+                images.and_then(|x| x.find_agent(id)).map(|x| x.name.clone())
+            });
+        });
+
+        ui.vertical(|ui| ui.add(egui::widgets::Separator::default().spacing(10.0)));
+
+        // Name timeline, newest first
+        ui.label(egui::RichText::new("Names:").strong());
+
+        let mut names: Vec<_> = player.name_history.iter().collect();
+        names.sort_by_key(|x| std::cmp::Reverse((x.name_time.unwrap_or(0), x.rowid)));
+
+        for name in names {
+            let seen = match name.name_time {
+                Some(name_time) => formatter.convert(Duration::from_secs((time_now as i64 - name_time).max(0) as u64)),
+                None => "unknown".to_string(),
+            };
+
+            ui.label(format!("{}#{} (first seen {})", name.name, name.tag, seen));
+        }
+
+        ui.vertical(|ui| ui.add(egui::widgets::Separator::default().spacing(10.0)));
+
+        // Match history, newest first
+        ui.horizontal(|ui| {
+            ui.label(egui::RichText::new("Matches:").strong());
+
+            if ui.add_enabled(profile.page > 0, egui::Button::new("<")).clicked() {
+                profile.page -= 1;
+            }
+
+            ui.label(format!("Page {} of {}", profile.page + 1, profile.page_count()));
+
+            if ui.add_enabled(profile.page + 1 < profile.page_count(), egui::Button::new(">")).clicked() {
+                profile.page += 1;
+            }
+        });
+
+        for log in profile.player.match_history.iter().rev().skip(profile.page * MATCHES_PER_PAGE).take(MATCHES_PER_PAGE) {
+            player::show_match_row(ui, log, images, time_now);
+            ui.add_space(5.0);
+        }
+    });
+
+    if back {
+        app.page = profile.back.clone();
+    }
+
+    app.apply_panel(response);
+}
@@ -0,0 +1,218 @@
+use std::time::Duration;
+use eframe::egui;
+use eframe::egui::{Color32, Id, Sense, Ui, Vec2};
+use poll_promise::Promise;
+use crate::{LoadedPlayer, MyApp};
+use crate::database;
+use crate::database::{DbError, SearchResult};
+use crate::display::player::{self, PanelResponse};
+use crate::r#match::PlayerHistory;
+
+const RESULT_LIMIT: usize = 50;
+const RECENT_LIMIT: i64 = 20;
+
+#[derive(Default)]
+pub struct Search {
+    pub query: String,
+    pub results: Vec<SearchResult>,
+    // Whether results are from a finished search, so an empty list means nothing matched
+    searched: bool,
+    error: Option<DbError>,
+    promise: Option<Promise<Result<Vec<SearchResult>, DbError>>>,
+    // The result whose history panel is open
+    pub selected: Option<LoadedPlayer>,
+    selected_promise: Option<Promise<LoadedPlayer>>,
+    // Shown before searching, None until loaded
+    pub recent: Option<Vec<SearchResult>>,
+    recent_promise: Option<Promise<Result<Vec<SearchResult>, DbError>>>,
+}
+
+impl Search {
+    fn start(&mut self) {
+        let query = self.query.clone();
+
+        self.selected = None;
+        self.selected_promise = None;
+        self.promise = Some(Promise::spawn_thread("search", move || database::search_players(&query, RESULT_LIMIT)));
+    }
+
+    fn poll(&mut self) {
+        if let Some(promise) = self.promise.take() {
+            match promise.try_take() {
+                Ok(Ok(results)) => {
+                    self.results = results;
+                    self.searched = true;
+                    self.error = None;
+                }
+                Ok(Err(err)) => self.error = Some(err),
+                Err(promise) => self.promise = Some(promise),
+            }
+        }
+
+        if let Some(promise) = self.selected_promise.take() {
+            match promise.try_take() {
+                Ok(player) => self.selected = Some(player),
+                Err(promise) => self.selected_promise = Some(promise),
+            }
+        }
+
+        if let Some(promise) = self.recent_promise.take() {
+            match promise.try_take() {
+                Ok(recent) => {
+                    self.recent = Some(recent.unwrap_or_else(|err| {
+                        println!("Couldnt get recent players: {}", err);
+                        Vec::new()
+                    }));
+                }
+                Err(promise) => self.recent_promise = Some(promise),
+            }
+        }
+    }
+
+    fn loading(&self) -> bool {
+        self.promise.is_some() || self.selected_promise.is_some() || self.recent_promise.is_some()
+    }
+}
+
+fn load_player(result: &SearchResult, time_now: i64) -> Promise<LoadedPlayer> {
+    let result = result.clone();
+
+    Promise::spawn_thread("load_player", move || {
+        LoadedPlayer::from_history(result.uuid.clone(), result.name, result.tag, PlayerHistory::load(result.uuid, time_now))
+    })
+}
+
+pub fn show_search(app: &mut MyApp, ui: &mut Ui) {
+    app.search.poll();
+
+    ui.horizontal(|ui| {
+        let res = ui.add(egui::TextEdit::singleline(&mut app.search.query).hint_text("Name, name#tag or puuid"));
+
+        let submitted = res.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+
+        if ui.add_enabled(app.search.promise.is_none(), egui::Button::new("Search")).clicked() || submitted {
+            app.search.start();
+        }
+    });
+
+    if app.search.promise.is_some() {
+        ui.label("Searching...");
+    }
+
+    if app.search.loading() {
+        ui.ctx().request_repaint();
+    }
+
+    if let Some(err) = &app.search.error {
+        ui.colored_label(Color32::GRAY, format!("Search failed: {}", err));
+    }
+
+    ui.vertical(|ui| ui.add(egui::widgets::Separator::default().spacing(10.0)));
+
+    if !app.search.searched {
+        show_recent(app, ui);
+        return
+    }
+
+    let time_now = app.settings.time_now();
+    let mut clicked = None;
+    let mut panel = PanelResponse::default();
+
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        for (i, result) in app.search.results.iter().enumerate() {
+            let res = ui.interact(egui::Rect::from_min_size(ui.next_widget_position(), Vec2::new(ui.available_width(), 40.0)), Id::new(format!("search_{}", i)), Sense::click());
+            let frame_color = if res.hovered() { Color32::from_rgb(41, 41, 41) } else { Color32::from_rgb(31, 31, 31) };
+
+            if res.hovered() {
+                ui.ctx().output_mut(|o| o.cursor_icon = egui::CursorIcon::PointingHand);
+            }
+
+            if res.clicked() {
+                clicked = Some(i);
+            }
+
+            egui::Frame::none()
+                .fill(frame_color)
+                .rounding(10.0)
+                .inner_margin(10.0)
+                .show(ui, |ui| {
+                    ui.set_width(ui.available_width());
+
+                    ui.horizontal(|ui| {
+                        let name = if result.name.is_empty() { result.uuid.clone() } else { format!("{}#{}", result.name, result.tag) };
+                        ui.colored_label(Color32::WHITE, name);
+
+                        if let Some(matched) = &result.matched {
+                            ui.colored_label(Color32::GRAY, format!("(was {})", matched));
+                        }
+
+                        ui.colored_label(Color32::GRAY, format!("Played {} times", result.times_played));
+                    });
+                });
+
+            if let Some(selected) = &app.search.selected {
+                if selected.uuid == result.uuid {
+                    panel = player::show_player_panel(ui, selected, &mut app.note_edit, &mut app.flag_edit, app.images.as_ref(), time_now);
+                }
+            }
+
+            ui.vertical(|ui| ui.add(egui::widgets::Separator::default().spacing(10.0)));
+        }
+
+        if app.search.results.is_empty() {
+            ui.colored_label(Color32::GRAY, "No players found");
+        }
+    });
+
+    if let Some(i) = clicked {
+        let result = &app.search.results[i];
+
+        // Clicking the open result again closes it
+        match &app.search.selected {
+            Some(selected) if selected.uuid == result.uuid => app.search.selected = None,
+            _ => app.search.selected_promise = Some(load_player(result, time_now as i64)),
+        }
+    }
+
+    app.apply_panel(panel);
+}
+
+// Latest players played with, clicking one opens their profile
+fn show_recent(app: &mut MyApp, ui: &mut Ui) {
+    if app.search.recent.is_none() && app.search.recent_promise.is_none() {
+        let puuid = app.auth.as_ref().map(|x| x.puuid.clone()).unwrap_or_default();
+
+        app.search.recent_promise = Some(Promise::spawn_thread("recent_players", move || database::recent_players(&puuid, RECENT_LIMIT)));
+    }
+
+    let time_now = app.settings.time_now();
+    let formatter = timeago::Formatter::new();
+    let mut open = None;
+
+    ui.label(egui::RichText::new("Recent encounters:").strong());
+
+    if app.search.recent_promise.is_some() {
+        ui.label("Loading...");
+        ui.ctx().request_repaint();
+    }
+
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        for result in app.search.recent.iter().flatten() {
+            let name = if result.name.is_empty() { result.uuid.clone() } else { format!("{}#{}", result.name, result.tag) };
+
+            ui.horizontal(|ui| {
+                if ui.link(name).clicked() {
+                    open = Some(result.uuid.clone());
+                }
+
+                ui.colored_label(Color32::GRAY, format!("Played {} times", result.times_played));
+
+                ui.colored_label(Color32::GRAY, formatter.convert(Duration::from_secs((time_now as i64 - result.last_played).max(0) as u64)));
+            });
+        }
+    });
+
+    if let Some(uuid) = open {
+        app.open_profile(uuid);
+    }
+}
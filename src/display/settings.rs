@@ -1,7 +1,8 @@
 use eframe::egui;
 use eframe::egui::Ui;
 use poll_promise::Promise;
-use crate::{converter, MyApp, State};
+use crate::{converter, database, MyApp, Settings, State};
+use crate::display::home;
 
 pub fn show_settings(app: &mut MyApp, ui: &mut Ui) {
     ui.horizontal(|ui| {
@@ -9,6 +10,65 @@ pub fn show_settings(app: &mut MyApp, ui: &mut Ui) {
         ui.checkbox(&mut app.settings.auto_refresh, "");
     });
 
+    ui.horizontal(|ui| {
+        ui.label("Refresh every: ");
+        ui.add(egui::DragValue::new(&mut app.settings.refresh_time).clamp_range(Settings::MIN_INTERVAL..=Settings::MAX_INTERVAL).suffix("s"));
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Check for Valorant every: ");
+        ui.add(egui::DragValue::new(&mut app.settings.wait_time).clamp_range(Settings::MIN_INTERVAL..=Settings::MAX_INTERVAL).suffix("s"));
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Flash taskbar for flagged players: ");
+        ui.checkbox(&mut app.settings.flag_notifications, "");
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Hide players met fewer than: ");
+        ui.add(egui::DragValue::new(&mut app.settings.min_times_played).clamp_range(0..=100).suffix(" times"));
+    });
+
+    home::show_filters(ui, &mut app.settings);
+
+    ui.vertical(|ui| ui.add(egui::widgets::Separator::default().spacing(10.0)));
+
+    ui.label("AppData/Local folder (leave empty to search automatically):");
+    ui.text_edit_singleline(&mut app.settings.app_data_path);
+
+    if let Some(auth) = &app.auth {
+        if let Some(lockfile) = &auth.lockfile_path {
+            ui.label(format!("Lockfile: {}", lockfile));
+        }
+
+        if let Some(log) = &auth.shooter_game_log_path {
+            ui.label(format!("Log: {}", log));
+        }
+    }
+
+    ui.collapsing("Endpoints", |ui| {
+        ui.label("Leave empty for the Riot defaults, {region}, {cluster} and {shard} are filled in.");
+
+        egui::Grid::new("endpoints").num_columns(2).show(ui, |ui| {
+            let endpoints = &mut app.settings.endpoints;
+
+            for (label, value) in [
+                ("glz", &mut endpoints.glz),
+                ("pd", &mut endpoints.pd),
+                ("shared", &mut endpoints.shared),
+                ("auth", &mut endpoints.auth),
+                ("glz cluster", &mut endpoints.glz_cluster),
+            ] {
+                ui.label(label);
+                ui.text_edit_singleline(value);
+                ui.end_row();
+            }
+        });
+
+        ui.label("Changes apply the next time Val+ connects to the Riot Client.");
+    });
+
     ui.vertical(|ui| ui.add(egui::widgets::Separator::default().spacing(10.0)));
 
     if ui.button("Import VRY data").clicked() {
@@ -30,4 +90,20 @@ pub fn show_settings(app: &mut MyApp, ui: &mut Ui) {
 
         app.state = State::CheckPromise;
     }
-}
\ No newline at end of file
+
+    ui.horizontal(|ui| {
+        let running = app.repair_promise.as_ref().is_some_and(|promise| promise.ready().is_none());
+
+        if ui.add_enabled(!running, egui::Button::new("Repair play counts")).clicked() {
+            app.repair_promise = Some(Promise::spawn_thread("repair_users", database::repair_users));
+        }
+
+        if let Some(promise) = &app.repair_promise {
+            match promise.ready() {
+                Some(Ok(count)) => ui.label(format!("Recounted {} players", count)),
+                Some(Err(err)) => ui.label(format!("Repair failed: {}", err)),
+                None => ui.label("Repairing..."),
+            };
+        }
+    });
+}
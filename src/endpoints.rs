@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_GLZ: &str = "https://glz-{region}-{cluster}.{shard}.a.pvp.net";
+const DEFAULT_PD: &str = "https://pd.{shard}.a.pvp.net";
+const DEFAULT_SHARED: &str = "https://shared.{shard}.a.pvp.net";
+const DEFAULT_AUTH: &str = "https://auth.riotgames.com";
+
+// Base URL overrides, e.g. to point every call at a local proxy.
+// Empty fields use the Riot defaults, and "{region}", "{cluster}" and "{shard}" are filled in.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Endpoints {
+    pub glz: String,
+    pub pd: String,
+    pub shared: String,
+    pub auth: String,
+    // Replaces the "-1" after the region in glz URLs, empty to use the one from ShooterGame.log
+    pub glz_cluster: String,
+}
+
+impl Endpoints {
+    // Environment variables take priority over settings
+    pub fn with_env(&self) -> Self {
+        let var = |name: &str, fallback: &String| std::env::var(name).unwrap_or_else(|_| fallback.clone());
+
+        Self {
+            glz: var("VALPLUS_GLZ_URL", &self.glz),
+            pd: var("VALPLUS_PD_URL", &self.pd),
+            shared: var("VALPLUS_SHARED_URL", &self.shared),
+            auth: var("VALPLUS_AUTH_URL", &self.auth),
+            glz_cluster: var("VALPLUS_GLZ_CLUSTER", &self.glz_cluster),
+        }
+    }
+
+    fn base<'a>(value: &'a str, default: &'a str) -> &'a str {
+        match value.trim() {
+            "" => default,
+            value => value.trim_end_matches('/'),
+        }
+    }
+
+    pub fn glz_url(&self, region: &str, cluster: &str, shard: &str) -> String {
+        let cluster = match self.glz_cluster.trim() {
+            "" => cluster,
+            cluster_override => cluster_override,
+        };
+
+        Self::base(&self.glz, DEFAULT_GLZ)
+            .replace("{region}", region)
+            .replace("{cluster}", cluster)
+            .replace("{shard}", shard)
+    }
+
+    pub fn pd_url(&self, shard: &str) -> String {
+        Self::base(&self.pd, DEFAULT_PD).replace("{shard}", shard)
+    }
+
+    pub fn shared_url(&self, shard: &str) -> String {
+        Self::base(&self.shared, DEFAULT_SHARED).replace("{shard}", shard)
+    }
+
+    pub fn auth_url(&self) -> String {
+        Self::base(&self.auth, DEFAULT_AUTH).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_to_riot() {
+        let endpoints = Endpoints::default();
+
+        assert_eq!(endpoints.glz_url("eu", "1", "eu"), "https://glz-eu-1.eu.a.pvp.net");
+        assert_eq!(endpoints.pd_url("na"), "https://pd.na.a.pvp.net");
+        assert_eq!(endpoints.shared_url("ap"), "https://shared.ap.a.pvp.net");
+        assert_eq!(endpoints.auth_url(), "https://auth.riotgames.com");
+    }
+
+    #[test]
+    fn overrides_base_and_cluster() {
+        let endpoints = Endpoints {
+            glz: "http://127.0.0.1:8080/glz/{region}/{shard}/".to_string(),
+            pd: "http://127.0.0.1:8080/pd".to_string(),
+            glz_cluster: "2".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(endpoints.glz_url("eu", "1", "eu"), "http://127.0.0.1:8080/glz/eu/eu");
+        assert_eq!(endpoints.pd_url("eu"), "http://127.0.0.1:8080/pd");
+
+        let endpoints = Endpoints { glz_cluster: "2".to_string(), ..Default::default() };
+        assert_eq!(endpoints.glz_url("na", "1", "na"), "https://glz-na-2.na.a.pvp.net");
+    }
+}
@@ -1,4 +1,4 @@
-use reqwest::blocking::Client;
+use reqwest::Client;
 #[derive(serde::Deserialize, Debug, Default, Clone)]
 pub struct AgentDetail {
     pub data: Vec<AgentDetailData>,
@@ -51,45 +51,43 @@ impl ImageData {
         }
     }
 
-    pub fn get_agents(&mut self) -> Result<(), ()> {
-        return match self.client.get("https://valorant-api.com/v1/agents?isPlayableCharacter=true")
-            .send()
-        {
-            Ok(res) => {
-                if res.status().is_success() {
-                    match res.json::<AgentDetail>() {
-                        Ok(json) => {
-                            self.agents = json.data.clone();
-                            Ok(())
-                        },
-                        Err(_) => Err(())
-                    }
-                } else {
-                    Err(())
-                }
-            },
-            Err(_) => return Err(()),
-        }
+    // Agents and maps don't depend on each other, so both are fetched at once
+    pub async fn load(&mut self) -> Result<(), ()> {
+        let (agents, maps) = tokio::join!(
+            self.fetch::<AgentDetail>("https://valorant-api.com/v1/agents?isPlayableCharacter=true"),
+            self.fetch::<MapDetail>("https://valorant-api.com/v1/maps"),
+        );
+
+        self.agents = agents?.data;
+        self.maps = maps?.data;
+
+        Ok(())
+    }
+
+    // Agents are stored by uuid, or by name for matches imported from VRY
+    pub fn find_agent(&self, agent_id: &str) -> Option<&AgentDetailData> {
+        let agent_id = agent_id.to_lowercase();
+
+        self.agents.iter().find(|x| x.uuid == agent_id || x.name.to_lowercase() == agent_id)
+    }
+
+    // Maps are stored by path, or by name for matches imported from VRY
+    pub fn find_map(&self, map_id: &str) -> Option<&MapDetailData> {
+        let map_id = map_id.trim().to_lowercase();
+
+        self.maps.iter().find(|x| x.path.trim().to_lowercase() == map_id || x.name.to_lowercase() == map_id.trim_matches('\"'))
     }
 
-    pub fn get_maps(&mut self) -> Result<(), ()> {
-        return match self.client.get("https://valorant-api.com/v1/maps")
-            .send()
-        {
+    async fn fetch<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T, ()> {
+        match self.client.get(url).send().await {
             Ok(res) => {
                 if res.status().is_success() {
-                    match res.json::<MapDetail>() {
-                        Ok(json) => {
-                            self.maps = json.data.clone();
-                            Ok(())
-                        },
-                        Err(_) => Err(())
-                    }
+                    res.json::<T>().await.map_err(|_| ())
                 } else {
                     Err(())
                 }
             },
-            Err(_) => return Err(()),
+            Err(_) => Err(()),
         }
     }
 
@@ -1,10 +1,86 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
 use regex::Regex;
-use reqwest::blocking::Client;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use crate::api::{LocalApi, LocalClient};
+use crate::discovery::{Discovery, Found};
+use crate::endpoints::Endpoints;
 
-#[derive(Debug, Default)]
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+// Contents of the Riot Client lockfile, "name:pid:port:password:protocol"
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct Lockfile {
-    pub port: String,
+    pub name: String,
+    pub pid: u32,
+    pub port: u16,
     pub password: String,
+    pub protocol: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LockfileError {
+    FieldCount(usize),
+    EmptyField(&'static str),
+    InvalidPid(String),
+    InvalidPort(String),
+    UnknownProtocol(String),
+}
+
+impl fmt::Display for LockfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockfileError::FieldCount(count) => write!(f, "expected 5 fields, found {}", count),
+            LockfileError::EmptyField(field) => write!(f, "{} is empty", field),
+            LockfileError::InvalidPid(pid) => write!(f, "invalid pid {:?}", pid),
+            LockfileError::InvalidPort(port) => write!(f, "invalid port {:?}", port),
+            LockfileError::UnknownProtocol(protocol) => write!(f, "unknown protocol {:?}", protocol),
+        }
+    }
+}
+
+impl std::error::Error for LockfileError {}
+
+impl Lockfile {
+    pub fn parse(contents: &str) -> Result<Self, LockfileError> {
+        let fields: Vec<&str> = contents.trim().split(':').collect();
+
+        let [name, pid, port, password, protocol] = fields[..] else {
+            return Err(LockfileError::FieldCount(fields.len()))
+        };
+
+        if name.is_empty() {
+            return Err(LockfileError::EmptyField("name"))
+        }
+
+        if password.is_empty() {
+            return Err(LockfileError::EmptyField("password"))
+        }
+
+        let pid = pid.parse::<u32>().map_err(|_| LockfileError::InvalidPid(pid.to_string()))?;
+
+        let port = match port.parse::<u16>() {
+            Ok(port) if port != 0 => port,
+            _ => return Err(LockfileError::InvalidPort(port.to_string())),
+        };
+
+        if protocol != "https" && protocol != "http" {
+            return Err(LockfileError::UnknownProtocol(protocol.to_string()))
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            pid,
+            port,
+            password: password.to_string(),
+            protocol: protocol.to_string(),
+        })
+    }
 }
 
 #[derive(serde::Deserialize)]
@@ -33,150 +109,381 @@ pub struct Authorization {
     pub token: String,
 }
 
+#[derive(serde::Deserialize)]
+struct JwtClaims {
+    exp: i64,
+}
+
+// Refresh this many seconds before the tokens actually expire
+const REFRESH_MARGIN: i64 = 120;
+// Used when a token has no readable "exp" claim, Riot access tokens last an hour
+const DEFAULT_TOKEN_LIFETIME: i64 = 3600;
+
+#[derive(Debug, Default, Clone)]
+pub struct Tokens {
+    pub access_token: String,
+    pub token: String,
+    // Unix time the first of the two tokens expires
+    pub expires_at: i64,
+}
+
+impl Tokens {
+    fn new(auth: Authorization) -> Self {
+        let expires_at = match (jwt_expiry(&auth.access_token), jwt_expiry(&auth.token)) {
+            (Some(access), Some(entitlement)) => access.min(entitlement),
+            (Some(exp), None) | (None, Some(exp)) => exp,
+            (None, None) => time_now() + DEFAULT_TOKEN_LIFETIME,
+        };
+
+        Self {
+            access_token: auth.access_token,
+            token: auth.token,
+            expires_at,
+        }
+    }
+
+    pub fn is_expiring(&self) -> bool {
+        self.expires_at - time_now() < REFRESH_MARGIN
+    }
+}
+
+fn time_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
+}
+
+// Reads the "exp" claim out of a JWT payload, the signature is not checked
+fn jwt_expiry(jwt: &str) -> Option<i64> {
+    let payload = jwt.split('.').nth(1)?;
+    let bytes = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+
+    serde_json::from_slice::<JwtClaims>(&bytes).ok().map(|claims| claims.exp)
+}
+
 #[derive(Debug)]
 pub enum LoaderError {
-    Auth, PortPassword, RegionShard, ClientVersion, PlayerInfo, NotLoaded
+    LockfileNotFound { searched: Vec<PathBuf> },
+    MalformedLockfile { path: PathBuf, source: LockfileError },
+    ShooterGameLogNotFound { searched: Vec<PathBuf> },
+    RegionShardNotFound { path: PathBuf },
+    Io { path: PathBuf, source: std::io::Error },
+    Request { endpoint: &'static str, source: reqwest::Error },
+    Status { endpoint: &'static str, status: StatusCode },
+    Deserialize { endpoint: &'static str, source: reqwest::Error },
+}
+
+impl fmt::Display for LoaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoaderError::LockfileNotFound { searched } => write!(f, "Riot Client lockfile not found ({} locations searched), is the Riot Client running?", searched.len()),
+            LoaderError::MalformedLockfile { path, source } => write!(f, "Malformed lockfile {}: {}", path.display(), source),
+            LoaderError::ShooterGameLogNotFound { searched } => write!(f, "ShooterGame.log not found ({} locations searched), has Valorant been started?", searched.len()),
+            LoaderError::RegionShardNotFound { path } => write!(f, "No region or shard found in {}", path.display()),
+            LoaderError::Io { path, source } => write!(f, "Could not read {}: {}", path.display(), source),
+            LoaderError::Request { endpoint, source } => write!(f, "Request to {} failed: {}", endpoint, source),
+            LoaderError::Status { endpoint, status } => write!(f, "{} returned {}", endpoint, status),
+            LoaderError::Deserialize { endpoint, source } => write!(f, "Unexpected response from {}: {}", endpoint, source),
+        }
+    }
+}
+
+impl std::error::Error for LoaderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoaderError::MalformedLockfile { source, .. } => Some(source),
+            LoaderError::Io { source, .. } => Some(source),
+            LoaderError::Request { source, .. } | LoaderError::Deserialize { source, .. } => Some(source),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Default)]
 pub struct Loader {
     client: Client,
+    local: Box<dyn LocalApi>,
+    discovery: Discovery,
 
-    pub port: String,
-    pub password: String,
+    pub lockfile_path: Option<Found>,
+    pub shooter_game_log_path: Option<Found>,
+
+    pub lockfile: Lockfile,
+    pub endpoints: Endpoints,
     pub region: String,
+    pub cluster: String,
     pub shard: String,
-    pub access_token: String,
-    pub token: String,
     pub client_version: String,
     pub puuid: String,
+
+    // Swapped as a whole on refresh, requests take a copy with tokens()
+    tokens: RwLock<Tokens>,
 }
 
 impl Loader {
-    pub fn new() -> Self {
+    // app_data is an explicit "AppData/Local" folder from settings, searched before the defaults
+    pub fn new(app_data: Option<PathBuf>, endpoints: Endpoints) -> Self {
+        // Without timeouts a hung request would never reach RetryPolicy
+        let client = Client::builder()
+            .danger_accept_invalid_certs(true)
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap();
+
+        let local = LocalClient::new(client.clone(), endpoints.auth_url());
+
+        Self {
+            endpoints,
+            ..Self::with_local_api(Discovery::new(app_data), client, Box::new(local))
+        }
+    }
+
+    pub fn with_local_api(discovery: Discovery, client: Client, local: Box<dyn LocalApi>) -> Self {
         Self {
-            client: Client::builder()
-                .danger_accept_invalid_certs(true)
-                .build()
-                .unwrap(),
+            client,
+            local,
+            discovery,
             ..Default::default()
         }
     }
 
-    pub fn try_load(&mut self) -> Result<(), LoaderError> {
+    pub fn glz_url(&self) -> String {
+        self.endpoints.glz_url(&self.region, &self.cluster, &self.shard)
+    }
+
+    pub fn pd_url(&self) -> String {
+        self.endpoints.pd_url(&self.shard)
+    }
+
+    pub fn shared_url(&self) -> String {
+        self.endpoints.shared_url(&self.shard)
+    }
+
+    // Shares this loader's connection pool
+    pub fn http_client(&self) -> Client {
+        self.client.clone()
+    }
+
+    pub async fn try_load(&mut self) -> Result<(), LoaderError> {
         self.get_port_and_password()?;
-        self.get_auth()?;
         self.get_region_and_shard()?;
-        self.get_client_version()?;
-        self.get_player_info()?;
+
+        // Both only need the lockfile
+        let (_, host_app) = tokio::try_join!(self.get_auth(), self.local.external_sessions(&self.lockfile))?;
+        self.client_version = host_app.host_app.version;
+
+        self.get_player_info().await?;
 
         Ok(())
     }
 
-    pub fn get_auth(&mut self) -> Result<(), LoaderError> {
-        match self.client.get(format!("https://127.0.0.1:{}/entitlements/v1/token", self.port)).basic_auth("riot", Some(&self.password)).send() {
-            Ok(res) => {
-                if res.status().is_success() {
-                    match res.json::<Authorization>() {
-                        Ok(auth) => {
-                            self.token = auth.token.clone();
-                            self.access_token = auth.access_token.clone();
-
-                            return Ok(())
-                        }
-                        _ => {},
-                    }
-                }
-            }
-            _ => {},
+    pub async fn get_auth(&self) -> Result<(), LoaderError> {
+        let tokens = Tokens::new(self.local.entitlements(&self.lockfile).await?);
+        println!("Got new tokens, expiring at {}", tokens.expires_at);
+
+        *self.tokens.write().unwrap() = tokens;
+
+        Ok(())
+    }
+
+    pub fn tokens(&self) -> Tokens {
+        self.tokens.read().unwrap().clone()
+    }
+
+    pub async fn refresh_if_expiring(&self) -> Result<(), LoaderError> {
+        if self.tokens().is_expiring() {
+            return self.get_auth().await
         }
 
-        Err(LoaderError::Auth)
+        Ok(())
     }
 
-    pub fn get_port_and_password(&mut self) -> Result<(), LoaderError> {
-        match std::env::var("LOCALAPPDATA") {
-            Ok(path) => {
-                match std::fs::read_to_string(format!("{}{}", path, "\\Riot Games\\Riot Client\\Config\\lockfile")) {
-                    Ok(lockfile) => {
-                        let lock_split: Vec<&str> = lockfile.split(":").collect();
+    // Sends a request built with the current tokens, refreshing them first if they are about to expire.
+    // A 401 means they expired early, so the tokens are refreshed and the request is sent once more.
+    pub async fn send_authorized(&self, build: impl Fn(&Tokens) -> RequestBuilder) -> reqwest::Result<Response> {
+        if let Err(err) = self.refresh_if_expiring().await {
+            println!("Token refresh failed: {}", err);
+        }
 
-                        self.port = lock_split.get(2).unwrap().to_string();
-                        self.password = lock_split.get(3).unwrap().to_string();
+        let res = build(&self.tokens()).send().await?;
 
-                        return Ok(())
+        if res.status() != StatusCode::UNAUTHORIZED {
+            return Ok(res)
+        }
 
-                    }
-                    _ => {}
-                }
+        match self.get_auth().await {
+            Ok(_) => build(&self.tokens()).send().await,
+            Err(err) => {
+                println!("Token refresh after 401 failed: {}", err);
+                Ok(res)
             }
-            _ => {}
         }
+    }
+
+    pub fn get_port_and_password(&mut self) -> Result<(), LoaderError> {
+        let found = self.discovery.find_lockfile()
+            .map_err(|searched| LoaderError::LockfileNotFound { searched })?;
+
+        println!("Using lockfile: {}", found);
+
+        let lockfile = std::fs::read_to_string(&found.path)
+            .map_err(|source| LoaderError::Io { path: found.path.clone(), source })?;
 
-        Err(LoaderError::PortPassword)
+        self.lockfile = Lockfile::parse(&lockfile)
+            .map_err(|source| LoaderError::MalformedLockfile { path: found.path.clone(), source })?;
+        self.lockfile_path = Some(found);
+
+        Ok(())
     }
 
     pub fn get_region_and_shard(&mut self) -> Result<(), LoaderError> {
-        match std::env::var("LOCALAPPDATA") {
-            Ok(path) => {
-                match std::fs::read_to_string(format!("{}{}", path, "\\VALORANT\\Saved\\Logs\\ShooterGame.log")) {
-                    Ok(shooter_game) => {
-                        let re = Regex::new(r"https://glz-(.+?)-1.(.+?).a.pvp.net").unwrap();
-
-                        if let Some(capture) = re.captures(&shooter_game) {
-                            if let (Some(region), Some(shard)) =
-                                (capture.get(1), capture.get(2)) {
-
-                                self.region = region.as_str().to_string();
-                                self.shard = shard.as_str().to_string();
-
-                                return Ok(())
-                            }
-                        }
-
-                    }
-                    _ => {}
-                }
+        let found = self.discovery.find_shooter_game_log()
+            .map_err(|searched| LoaderError::ShooterGameLogNotFound { searched })?;
+
+        println!("Using ShooterGame.log: {}", found);
+
+        let shooter_game = std::fs::read_to_string(&found.path)
+            .map_err(|source| LoaderError::Io { path: found.path.clone(), source })?;
+
+        let re = Regex::new(r"https://glz-(.+?)-(\d+)\.(.+?)\.a\.pvp\.net").unwrap();
+
+        if let Some(capture) = re.captures(&shooter_game) {
+            if let (Some(region), Some(cluster), Some(shard)) =
+                (capture.get(1), capture.get(2), capture.get(3)) {
+
+                self.region = region.as_str().to_string();
+                self.cluster = cluster.as_str().to_string();
+                self.shard = shard.as_str().to_string();
+                self.shooter_game_log_path = Some(found);
+
+                return Ok(())
             }
-            _ => {}
         }
 
-        Err(LoaderError::RegionShard)
+        Err(LoaderError::RegionShardNotFound { path: found.path })
     }
 
-    pub fn get_client_version(&mut self) -> Result<(), LoaderError> {
-        match self.client.get(format!("https://127.0.0.1:{}/product-session/v1/external-sessions", &self.port)).basic_auth("riot", Some(&self.password)).send() {
-            Ok(res) => {
-                match res.json::<HostApp>() {
-                    Ok(json) => {
-                        self.client_version = json.host_app.version;
+    pub async fn get_player_info(&mut self) -> Result<(), LoaderError> {
+        self.puuid = self.local.user_info(&self.tokens().access_token).await?.puuid;
 
-                        return Ok(())
-                    }
-                    _ => {}
-                }
-            }
-            _ => {}
-        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::fixtures::{FixtureLocalApi, PUUID};
+    use crate::discovery::PathSource;
 
-        Err(LoaderError::ClientVersion)
+    fn jwt(claims: &str) -> String {
+        format!("eyJhbGciOiJSUzI1NiJ9.{}.c2lnbmF0dXJl", URL_SAFE_NO_PAD.encode(claims))
     }
 
-    pub fn get_player_info(&mut self) -> Result<(), LoaderError> {
-        match self.client.get("https://auth.riotgames.com/userinfo").bearer_auth(&self.access_token).send() {
-            Ok(res) => {
-                match res.json::<UserId>() {
-                    Ok(json) => {
-                        self.puuid = json.puuid;
+    #[test]
+    fn reads_jwt_expiry() {
+        assert_eq!(jwt_expiry(&jwt(r#"{"sub":"abc","exp":1718000000}"#)), Some(1718000000));
+        assert_eq!(jwt_expiry(&jwt(r#"{"sub":"abc"}"#)), None);
+        assert_eq!(jwt_expiry("not a jwt"), None);
+    }
 
-                        return Ok(())
-                    }
-                    _ => {}
-                }
-            }
-            _ => {}
-        }
+    #[test]
+    fn tokens_expire_with_the_earliest_jwt() {
+        let tokens = Tokens::new(Authorization {
+            access_token: jwt(r#"{"exp":2000}"#),
+            token: jwt(r#"{"exp":1000}"#),
+        });
+
+        assert_eq!(tokens.expires_at, 1000);
+        assert!(tokens.is_expiring());
+    }
+
+    #[test]
+    fn parses_lockfile() {
+        let lockfile = Lockfile::parse("Riot Client:12764:50823:Yt7-dB_2x9qLmN0aPk4wZg:https").unwrap();
+
+        assert_eq!(lockfile, Lockfile {
+            name: "Riot Client".to_string(),
+            pid: 12764,
+            port: 50823,
+            password: "Yt7-dB_2x9qLmN0aPk4wZg".to_string(),
+            protocol: "https".to_string(),
+        });
+    }
+
+    #[test]
+    fn ignores_trailing_newline() {
+        let lockfile = Lockfile::parse("Riot Client:1:443:password:https\r\n").unwrap();
+
+        assert_eq!(lockfile.port, 443);
+        assert_eq!(lockfile.protocol, "https");
+    }
+
+    #[test]
+    fn rejects_truncated_lockfile() {
+        assert_eq!(Lockfile::parse("Riot Client:12764:508"), Err(LockfileError::FieldCount(3)));
+        assert_eq!(Lockfile::parse(""), Err(LockfileError::FieldCount(1)));
+    }
+
+    #[test]
+    fn rejects_extra_fields() {
+        assert_eq!(Lockfile::parse("Riot Client:1:443:pass:word:https"), Err(LockfileError::FieldCount(6)));
+    }
 
-        Err(LoaderError::PlayerInfo)
+    #[test]
+    fn rejects_invalid_port() {
+        assert_eq!(Lockfile::parse("Riot Client:1:0:password:https"), Err(LockfileError::InvalidPort("0".to_string())));
+        assert_eq!(Lockfile::parse("Riot Client:1:70000:password:https"), Err(LockfileError::InvalidPort("70000".to_string())));
+        assert_eq!(Lockfile::parse("Riot Client:1::password:https"), Err(LockfileError::InvalidPort("".to_string())));
+    }
+
+    #[test]
+    fn rejects_invalid_pid() {
+        assert_eq!(Lockfile::parse("Riot Client:-1:443:password:https"), Err(LockfileError::InvalidPid("-1".to_string())));
+    }
+
+    #[test]
+    fn rejects_empty_fields() {
+        assert_eq!(Lockfile::parse(":1:443:password:https"), Err(LockfileError::EmptyField("name")));
+        assert_eq!(Lockfile::parse("Riot Client:1:443::https"), Err(LockfileError::EmptyField("password")));
+    }
+
+    #[test]
+    fn rejects_unknown_protocol() {
+        assert_eq!(Lockfile::parse("Riot Client:1:443:password:ftp"), Err(LockfileError::UnknownProtocol("ftp".to_string())));
+    }
+
+    #[tokio::test]
+    async fn loads_from_fixtures() {
+        let app_data = std::env::temp_dir().join(format!("valplus_loader_{}", std::process::id()));
+        let config = app_data.join("Riot Games").join("Riot Client").join("Config");
+        let logs = app_data.join("VALORANT").join("Saved").join("Logs");
+
+        std::fs::create_dir_all(&config).unwrap();
+        std::fs::create_dir_all(&logs).unwrap();
+        std::fs::write(config.join("lockfile"), "Riot Client:12764:50823:password:https").unwrap();
+        std::fs::write(logs.join("ShooterGame.log"), "[2024.06.10] LogShooter: https://glz-eu-1.eu.a.pvp.net/session/v1/sessions\n").unwrap();
+
+        let mut loader = Loader::with_local_api(Discovery::from_dirs(vec![(app_data.clone(), PathSource::Settings)]), Client::new(), Box::new(FixtureLocalApi));
+        let result = loader.try_load().await;
+
+        std::fs::remove_dir_all(&app_data).unwrap();
+        result.unwrap();
+
+        assert_eq!(loader.lockfile.port, 50823);
+        assert_eq!((loader.region.as_str(), loader.cluster.as_str(), loader.shard.as_str()), ("eu", "1", "eu"));
+        assert_eq!(loader.client_version, "release-08.11-shipping-6-2579366");
+        assert_eq!(loader.puuid, PUUID);
+        assert!(!loader.tokens().is_expiring());
+    }
+
+    #[tokio::test]
+    async fn reports_missing_lockfile() {
+        let app_data = std::env::temp_dir().join(format!("valplus_missing_{}", std::process::id()));
+        let mut loader = Loader::with_local_api(Discovery::from_dirs(vec![(app_data.clone(), PathSource::Settings)]), Client::new(), Box::new(FixtureLocalApi));
+
+        match loader.try_load().await {
+            Err(LoaderError::LockfileNotFound { searched }) => assert_eq!(searched, vec![app_data.join("Riot Games").join("Riot Client").join("Config").join("lockfile")]),
+            other => panic!("expected a missing lockfile, got {:?}", other.map(|_| ())),
+        }
     }
 }
@@ -4,59 +4,120 @@
 extern crate self_update;
 
 use std::cmp::PartialEq;
+use std::path::PathBuf;
 use std::sync::Arc;
 use crate::loader::{Loader, LoaderError};
 
 use std::time;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{SystemTime, UNIX_EPOCH};
 use eframe::{CreationContext, egui, Storage};
 use eframe::egui::{Color32, Id, Layout, Pos2, Sense, Ui, Vec2};
 use poll_promise::Promise;
 use serde::{Deserialize, Serialize};
-use crate::database::{MatchHistory, NameHistory};
+use crate::database::{DbError, NameHistory, PlayerFlag, PlayerMatch, PlayerNote, EncounterStats};
+use crate::display::flag::{self, FlagAction, FlagEdit};
+use crate::display::home::{self, PlayerSort, TeamFilter};
+use crate::display::matches::{show_matches, MatchLog};
+use crate::display::note::{self, NoteAction, NoteEdit};
+use crate::display::player::{self, PanelResponse};
+use crate::display::profile::{show_profile, Profile};
+use crate::display::search::{show_search, Search};
 use crate::display::settings::show_settings;
+use crate::endpoints::Endpoints;
 use crate::images::ImageData;
-use crate::r#match::MatchHandler;
+use crate::r#match::{GameType, MatchError, MatchHandler, PlayerHistory};
+use crate::riot_client::{RiotClient, RiotError};
+use crate::watcher::{LockfileEvent, LockfileWatcher};
 
 mod display {
+    pub mod encounters;
+    pub mod flag;
     pub mod home;
+    pub mod matches;
+    pub mod note;
+    pub mod player;
+    pub mod profile;
+    pub mod search;
     pub mod settings;
 }
 
+mod api;
 mod loader;
+mod discovery;
+mod endpoints;
+mod watcher;
 mod pre_game;
 mod r#match;
+mod match_details;
 mod name_service;
+mod riot_client;
 mod database;
 mod images;
 mod converter;
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 struct LoadedPlayer {
     uuid: String,
     name: String,
     tag: String,
     team: TeamType,
 
-    match_history: Vec<MatchHistory>,
+    match_history: Vec<PlayerMatch>,
     name_history: Vec<NameHistory>,
 
     times_played: i64,
     last_played: i64,
+    note: Option<PlayerNote>,
+    flag: Option<PlayerFlag>,
+    flag_reason: String,
+    encounters: EncounterStats,
 
     agent_id: String,
     incognito: bool,
 }
 
-#[derive(Debug, Clone)]
+impl LoadedPlayer {
+    // A player looked up outside of a live match, shown with the agent they last played
+    fn from_history(uuid: String, name: String, tag: String, history: PlayerHistory) -> Self {
+        let agent_id = history.match_history.last().map(|x| x.participant.agent_id.clone()).unwrap_or_default();
+
+        Self {
+            uuid,
+            name,
+            tag,
+            team: TeamType::Ally,
+
+            match_history: history.match_history,
+            name_history: history.name_history,
+
+            times_played: history.times_played,
+            last_played: history.last_played,
+            note: history.note,
+            flag: history.flag,
+            flag_reason: history.flag_reason,
+            encounters: history.encounters,
+
+            agent_id,
+            incognito: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 enum TeamType {
+    #[default]
     Ally,
     Enemy
 }
 
 fn main() -> Result<(), eframe::Error> {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
+
+    // Shared by every request, promises are spawned onto it with Promise::spawn_async
+    let runtime = tokio::runtime::Runtime::new().expect("failed to start tokio runtime");
+    let _guard = runtime.enter();
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([350.0, 350.0])
@@ -79,7 +140,7 @@ fn main() -> Result<(), eframe::Error> {
 
             // This gives us image support:
             egui_extras::install_image_loaders(&cc.egui_ctx);
-            Box::<MyApp>::default()
+            Box::new(MyApp::new(cc))
         }),
     )
 }
@@ -87,16 +148,37 @@ fn main() -> Result<(), eframe::Error> {
 #[derive(Default)]
 struct MyApp {
     auth: Option<Arc<Loader>>,
+    riot: Option<RiotClient>,
+    loader_error: Option<LoaderError>,
+    lockfile_watcher: Option<LockfileWatcher>,
 
     state: State,
     page: Page,
 
     current_match: Option<MatchHandler>,
     settings: Settings,
-    selected_user: Option<u8>,
-
-    promise: Option<Promise<Option<MatchHandler>>>,
+    // Settings as last written to storage
+    saved_settings: Settings,
+    // Puuid of the player whose card is expanded
+    selected_user: Option<String>,
+    note_edit: Option<NoteEdit>,
+    flag_edit: Option<FlagEdit>,
+    // Match id and flagged puuids the taskbar last flashed for
+    notified_flags: Option<(String, Vec<String>)>,
+    search: Search,
+    profile: Option<Profile>,
+    // Profile being loaded by open_profile, shown once ready
+    profile_promise: Option<Promise<Profile>>,
+    match_log: MatchLog,
+
+    loader_promise: Option<Promise<Result<Loader, LoaderError>>>,
+    promise: Option<Promise<Result<MatchHandler, MatchError>>>,
+    match_error: Option<MatchError>,
+    // Core game match id results were last looked up after, None until the first lookup
+    results_checked: Option<String>,
+    results_promise: Option<Promise<Result<usize, RiotError>>>,
     import_promise: Option<Promise<(i32, i32, i32)>>,
+    repair_promise: Option<Promise<Result<usize, DbError>>>,
     image_promise: Option<Promise<Option<ImageData>>>,
     images: Option<ImageData>,
 }
@@ -116,29 +198,70 @@ enum State {
 enum Page {
     #[default]
     Home,
+    Search,
+    Matches,
     Settings,
+    // Profile of a puuid, loaded into MyApp::profile
+    Player(String),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// Saved to eframe storage, missing fields fall back to the defaults
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Settings {
     auto_refresh: bool,
+    // Flash the taskbar when a loaded match has a flagged player
+    flag_notifications: bool,
     wait_time: u64,
     refresh_time: u64,
+    #[serde(skip)]
     last_checked: u64,
+    // Players met fewer times are hidden from the home page unless show_all is set
+    min_times_played: i64,
+    show_all: bool,
+    team_filter: TeamFilter,
+    hide_incognito: bool,
+    sort: PlayerSort,
+    // Explicit "AppData/Local" folder, empty to search the default locations
+    app_data_path: String,
+    endpoints: Endpoints,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Self {
             auto_refresh: true,
+            flag_notifications: true,
             wait_time: 15,
             refresh_time: 10,
             last_checked: 0,
+            min_times_played: 3,
+            show_all: false,
+            team_filter: TeamFilter::All,
+            hide_incognito: false,
+            sort: PlayerSort::Team,
+            app_data_path: String::new(),
+            endpoints: Endpoints::default(),
         }
     }
 }
 
 impl Settings {
+    pub const MIN_INTERVAL: u64 = 1;
+    pub const MAX_INTERVAL: u64 = 600;
+
+    // Keeps values from storage or an older version in the ranges the settings page allows
+    pub fn validated(mut self) -> Self {
+        self.wait_time = self.wait_time.clamp(Self::MIN_INTERVAL, Self::MAX_INTERVAL);
+        self.refresh_time = self.refresh_time.clamp(Self::MIN_INTERVAL, Self::MAX_INTERVAL);
+        self.min_times_played = self.min_times_played.max(0);
+        self
+    }
+
+    // Whether anything that gets saved differs, last_checked is not saved
+    pub fn changed_from(&self, saved: &Settings) -> bool {
+        *self != Settings { last_checked: self.last_checked, ..saved.clone() }
+    }
 
     pub fn time_now(&self) -> u64 {
         SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
@@ -168,6 +291,16 @@ impl Settings {
         return false
     }
 
+    pub fn app_data_path(&self) -> Option<PathBuf> {
+        let path = self.app_data_path.trim();
+
+        if path.is_empty() {
+            return None
+        }
+
+        Some(PathBuf::from(path))
+    }
+
     pub fn get_refresh_time(&mut self) -> u64 {
         let time_now = self.time_now();
 
@@ -177,18 +310,36 @@ impl Settings {
 }
 
 impl MyApp {
+    fn new(cc: &CreationContext) -> Self {
+        let settings = cc.storage
+            .and_then(|storage| eframe::get_value::<Settings>(storage, eframe::APP_KEY))
+            .unwrap_or_default()
+            .validated();
+
+        Self {
+            saved_settings: settings.clone(),
+            settings,
+            ..Default::default()
+        }
+    }
+
     fn home_page(&mut self, ctx: &egui::Context, ui: &mut Ui) {
+        let mut panel = PanelResponse::default();
+
         if let Some(current_match) = &self.current_match {
             let players = &current_match.players;
 
+            flag::show_alerts(ui, current_match.flagged_players());
+            home::show_filters(ui, &mut self.settings);
+
             let formatter = timeago::Formatter::new();
 
             egui::ScrollArea::vertical().show(ui, |ui| {
-                for (i, player) in players.iter().filter(|x| x.times_played > 2).enumerate() {
+                for player in home::visible_players(players, &self.settings) {
 
                     //println!("{:?}", player.agent_id);
 
-                    let res = ui.interact(egui::Rect::from_min_size(ui.next_widget_position(), Vec2::new(ui.available_width(), 80.0)), Id::new(format!("area_{}", i)), Sense::click());
+                    let res = ui.interact(egui::Rect::from_min_size(ui.next_widget_position(), Vec2::new(ui.available_width(), 80.0)), Id::new(("area", &player.uuid)), Sense::click());
                     let mut frame_color = Color32::from_rgb(31, 31, 31);
 
 
@@ -202,16 +353,11 @@ impl MyApp {
                     if res.clicked() {
                         ui.scroll_to_rect(res.rect, Some(egui::Align::TOP));
 
-                        println!("clicked area: {:?}", i);
-                        if let Some(index) = self.selected_user {
-                            if i == index.to_owned() as usize {
-                                // USER CLICKED ALREADY SELECTED
-                                self.selected_user = None;
-                            }  else {
-                                self.selected_user = Some(i as u8);
-                            }
+                        // Clicking the open card again closes it
+                        if self.selected_user.as_ref() == Some(&player.uuid) {
+                            self.selected_user = None;
                         } else {
-                            self.selected_user = Some(i as u8);
+                            self.selected_user = Some(player.uuid.clone());
                         }
                     }
 
@@ -262,6 +408,18 @@ impl MyApp {
                                                 )
                                             }
                                         ).on_hover_text(format!("{} days", time_since / 86400));
+
+                                        if let Some(player_flag) = player.flag {
+                                            ui.colored_label(flag::flag_color(player_flag), player_flag.to_string());
+                                        }
+
+                                        if !player.encounters.win_loss.is_empty() {
+                                            ui.colored_label(Color32::GRAY, player.encounters.win_loss.to_string());
+                                        }
+
+                                        if let Some(note) = &player.note {
+                                            ui.colored_label(Color32::GRAY, note::note_summary(note));
+                                        }
                                     });
                                 });
                             });
@@ -271,158 +429,232 @@ impl MyApp {
 
                     // History
 
-                    if let Some(selected_user) = &self.selected_user {
-                        if i == selected_user.to_owned() as usize {
+                    if self.selected_user.as_ref() == Some(&player.uuid) {
+                        panel = player::show_player_panel(ui, player, &mut self.note_edit, &mut self.flag_edit, self.images.as_ref(), self.settings.time_now());
+                    }
+                    ui.vertical(|ui| ui.add(egui::widgets::Separator::default().spacing(10.0)));
+                }
+                ui.label("Made by: nedasv | Discord: 3eu");
+            });
+        } else {
+            if let Some(_) = &self.auth {
+                ui.add_space(ui.available_height() / 2.0 - 20.);
+
+                ui.horizontal(|ui| {
+                    ui.add_space((ui.available_width() / 2.0) - 65.);
+                    egui_twemoji::EmojiLabel::new("Waiting for a match 👀").show(ui);
+                });
 
+                if let Some(err) = &self.match_error {
+                    ui.vertical_centered(|ui| {
+                        ui.colored_label(Color32::GRAY, err.to_string());
+                    });
+                }
+            }
+        }
 
-                            // Match History
-                            if player.match_history.len() > 0usize {
-                                ui.vertical(|ui| ui.add(egui::widgets::Separator::default().spacing(10.0)));
+        self.apply_panel(panel);
+    }
 
-                                egui::Frame::none()
-                                    .fill(Color32::from_rgb(41, 41, 41))
-                                    .rounding(10.0)
-                                    .show(ui, |ui| {
-                                        ui.set_width(ui.available_width());
-                                        ui.set_height(50.0);
+    fn apply_panel(&mut self, response: PanelResponse) {
+        self.apply_note_action(response.note);
+        self.apply_flag_action(response.flag);
 
-                                        ui.vertical(|ui| {
-                                            ui.add_space(5.0);
+        if let Some(uuid) = response.open_profile {
+            self.open_profile(uuid);
+        }
+    }
 
-                                            if !player.incognito {
-                                                // Name History
-
-                                                if player.name_history.len() > 0usize { // 1 to ignore current name
-
-                                                    ui.horizontal(|ui| {
-                                                        ui.add_space(10.0);
-                                                        ui.label(egui::RichText::new("Old Usernames:").strong());
-                                                    });
-
-                                                    for name_history in &player.name_history {
-
-                                                        ui.horizontal(|ui| {
-                                                            ui.add_space(10.0);
-                                                            ui.label(
-                                                                format!("{}#{} ({})",
-                                                                        &name_history.name,
-                                                                        &name_history.tag,
-                                                                        formatter.convert(Duration::from_secs((self.settings.time_now() as i64 - name_history.name_time.clone().unwrap()).max(0) as u64)),
-                                                                )
-                                                            );
-                                                        });
-                                                    }
-                                                }
-
-                                                ui.vertical(|ui| ui.add(egui::widgets::Separator::default().spacing(10.0)));
-                                            }
+    // Loads everything recorded about a player in the background and shows their profile
+    fn open_profile(&mut self, uuid: String) {
+        // Going from one profile to another still goes back to the page before
+        let back = match &self.page {
+            Page::Player(_) => self.profile.as_ref().map_or(Page::Home, |x| x.back.clone()),
+            page => page.clone(),
+        };
+
+        let time_now = self.settings.time_now() as i64;
+        let player_uuid = uuid.clone();
+
+        self.profile_promise = Some(Promise::spawn_thread("open_profile", move || {
+            let name = match database::get_latest_name(player_uuid.clone()) {
+                Ok(name) => name.unwrap_or_default(),
+                Err(err) => {
+                    println!("Couldnt get name: {}", err);
+                    NameHistory::default()
+                }
+            };
 
-                                            ui.horizontal(|ui| {
-                                                ui.add_space(10.0);
-                                                ui.label(egui::RichText::new("First Played:").strong());
-                                                ui.label(format!("{}", formatter.convert(Duration::from_secs((self.settings.time_now() as i64 - player.match_history.first().unwrap().match_time).max(0) as u64))))
-                                            });
+            let history = PlayerHistory::load(player_uuid.clone(), time_now);
 
-                                            ui.horizontal(|ui| {
-                                                ui.add_space(10.0);
-                                                ui.label(egui::RichText::new("Played:").strong());
-                                                ui.label(format!("{} times", player.match_history.len()))
-                                            });
+            Profile::new(LoadedPlayer::from_history(player_uuid, name.name, name.tag, history), back)
+        }));
 
-                                            ui.add_space(5.0);
-                                        });
-                                    });
+        self.page = Page::Player(uuid);
+    }
+
+    // Every player shown with a history panel, so edits show up wherever they're open
+    fn loaded_players_mut(&mut self) -> impl Iterator<Item = &mut LoadedPlayer> {
+        self.current_match.iter_mut().flat_map(|x| x.players.iter_mut())
+            .chain(self.search.selected.iter_mut())
+            .chain(self.profile.iter_mut().map(|x| &mut x.player))
+    }
 
-                                ui.vertical(|ui| ui.add(egui::widgets::Separator::default().spacing(10.0)));
+    // Writes an inline note edit and updates the loaded player to match
+    fn apply_note_action(&mut self, action: NoteAction) {
+        let Some(edit) = self.note_edit.clone() else {
+            return
+        };
+
+        let note = match action {
+            NoteAction::None => return,
+            NoteAction::Cancel => {
+                self.note_edit = None;
+                return
+            }
+            NoteAction::Save => match database::save_note(edit.to_note()) {
+                Ok(note) => Some(note),
+                Err(err) => {
+                    println!("Failed to save note: {}", err);
+                    return
+                }
+            },
+            NoteAction::Delete => match database::delete_note(edit.uuid.clone()) {
+                Ok(_) | Err(DbError::NotFound) => None,
+                Err(err) => {
+                    println!("Failed to delete note: {}", err);
+                    return
+                }
+            },
+        };
 
-                                for log in player.match_history.iter().rev().take(10) {
-                                    println!("{:?}", log.agent_id);
+        for player in self.loaded_players_mut().filter(|x| x.uuid == edit.uuid) {
+            player.note = note.clone();
+        }
 
-                                   let (mut agent_image, mut agent_name) = (String::new(), String::new());
-                                   let (mut map_image, mut map_name) = (String::new(), String::new());
+        self.note_edit = None;
+    }
 
-                                    if let Some(images) = &self.images {
-                                        let agent = images.agents.iter().find(|x| x.uuid == log.agent_id.to_lowercase() || x.name.to_lowercase() == log.agent_id.to_lowercase());
+    // Writes an inline flag edit and updates the loaded player to match
+    fn apply_flag_action(&mut self, action: FlagAction) {
+        let Some(edit) = self.flag_edit.clone() else {
+            return
+        };
+
+        match action {
+            FlagAction::None => return,
+            FlagAction::Cancel => {
+                self.flag_edit = None;
+                return
+            }
+            FlagAction::Save => {
+                if let Err(err) = database::set_flag(edit.uuid.clone(), edit.flag, edit.reason.clone()) {
+                    println!("Failed to save flag: {}", err);
+                    return
+                }
+            }
+        }
 
-                                        if let Some(agent) = agent {
-                                            agent_image = agent.icon.clone();
-                                            agent_name = agent.name.clone();
-                                        }
+        let reason = if edit.flag.is_some() { edit.reason.trim().to_string() } else { String::new() };
 
-                                        let map = images.maps.iter().find(|x| x.path.trim().to_lowercase() == log.map_id.clone().trim().to_lowercase() || x.name.to_lowercase() == log.map_id.clone().to_lowercase().trim_matches('\"'));
+        for player in self.loaded_players_mut().filter(|x| x.uuid == edit.uuid) {
+            player.flag = edit.flag;
+            player.flag_reason = reason.clone();
+        }
 
-                                        if let Some(map) = map {
-                                            map_image = map.icon.clone();
-                                            map_name = map.name.clone();
-                                        }
-                                    }
+        self.flag_edit = None;
+    }
+}
 
-                                    let frame_color = if log.enemy.unwrap() { Color32::from_rgb(41, 31, 41) } else { Color32::from_rgb(31, 41, 41) };
-
-                                   egui::Frame::none()
-                                       .fill(frame_color)
-                                       .rounding(10.0)
-                                       .show(ui, |ui| {
-                                           ui.set_max_width(ui.available_width());
-                                           ui.set_max_height(80.0);
-
-                                           ui.horizontal(|ui| {
-                                               // Agent Icon
-                                               ui.add(
-                                                   egui::Image::new(agent_image)
-                                                       .fit_to_exact_size(Vec2::new(80.0, 80.0))
-                                                       .maintain_aspect_ratio(false)
-                                                       .rounding(10.0)
-                                               );
-
-                                               // Data
-                                               ui.vertical(|ui| {
-                                                   ui.add_space(15.0);
-                                                   ui.colored_label(Color32::WHITE, map_name);
-
-                                                   if log.enemy.unwrap() {
-                                                       ui.colored_label(Color32::RED, "Enemy");
-                                                   }  else {
-                                                       ui.colored_label(Color32::GREEN, "Team");
-                                                   }
-
-                                                   ui.colored_label(Color32::WHITE, format!("{}", formatter.convert(time::Duration::from_secs((self.settings.time_now() as i64 - log.match_time).max(0) as u64))));
-                                               });
-
-                                               ui.add_space(ui.available_width() - 80.0);
-
-                                               // Map Icon
-                                               ui.add(
-                                                   egui::Image::new(map_image)
-                                                       .fit_to_exact_size(Vec2::new(80.0, 80.0))
-                                                       .maintain_aspect_ratio(false)
-                                                       .rounding(10.0)
-                                               );
-                                           });
-                                       });
-                                }
-                            }
+impl MyApp {
+    // Drops the loaded credentials when the Riot Client restarts or closes, so they get loaded again
+    fn watch_lockfile(&mut self, ctx: &egui::Context) {
+        let Some(watcher) = &mut self.lockfile_watcher else {
+            return
+        };
+
+        ctx.request_repaint_after(watcher::POLL_INTERVAL);
+
+        match watcher.poll() {
+            LockfileEvent::Unchanged => return,
+            LockfileEvent::Changed => {
+                println!("Lockfile changed, reloading");
+                // Skip the wait so try_load runs on the next frame
+                self.settings.last_checked = 0;
+            }
+            LockfileEvent::Removed => {
+                println!("Lockfile removed, waiting for Valorant");
+                self.current_match = None;
+            }
+        }
+
+        self.reload();
+    }
+
+    // Forgets the credentials so they're loaded again from the lockfile
+    fn reload(&mut self) {
+        self.auth = None;
+        self.riot = None;
+        self.promise = None;
+        self.match_error = None;
+        self.lockfile_watcher = None;
+        self.state = State::WaitValorant;
+    }
+
+    // Not in a game any more, so the last core game (or anything from before a restart) may have results
+    fn check_results(&mut self) {
+        let Some(riot) = &self.riot else {
+            return
+        };
+
+        let ended = match &self.current_match {
+            Some(match_handler) if match_handler.game_type == GameType::CoreGame => match_handler.match_id.clone(),
+            _ => String::new(),
+        };
+
+        if self.results_promise.is_some() || self.results_checked.as_ref() == Some(&ended) {
+            return
+        }
+
+        self.results_checked = Some(ended);
+
+        let riot = riot.clone();
+        self.results_promise = Some(Promise::spawn_async(async move {
+            match_details::record_pending(&riot).await
+        }));
+    }
+
+    fn poll_results(&mut self) {
+        let Some(promise) = self.results_promise.take() else {
+            return
+        };
+
+        match promise.try_take() {
+            Ok(Ok(recorded)) => {
+                println!("Recorded results for {} matches", recorded);
+
+                if let Some(current_match) = &mut self.current_match {
+                    for player in current_match.players.iter_mut() {
+                        match database::encounter_stats(player.uuid.clone()) {
+                            Ok(encounters) => player.encounters = encounters,
+                            Err(err) => println!("Couldnt get encounter stats: {}", err),
                         }
                     }
-                    ui.vertical(|ui| ui.add(egui::widgets::Separator::default().spacing(10.0)));
                 }
-                ui.label("Made by: nedasv | Discord: 3eu");
-            });
-        } else {
-            if let Some(_) = &self.auth {
-                ui.add_space(ui.available_height() / 2.0 - 20.);
-
-                ui.horizontal(|ui| {
-                    ui.add_space((ui.available_width() / 2.0) - 65.);
-                    egui_twemoji::EmojiLabel::new("Waiting for a match 👀").show(ui);
-                });
             }
+            Ok(Err(err)) => println!("Match results error: {}", err),
+            Err(promise) => self.results_promise = Some(promise),
         }
     }
 }
 
 impl eframe::App for MyApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    fn save(&mut self, storage: &mut dyn Storage) {
+        eframe::set_value(storage, eframe::APP_KEY, &self.settings);
+        self.saved_settings = self.settings.clone();
+    }
+
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         egui::CentralPanel::default().show(ctx, |ui| {
 
             // Nav Bar
@@ -431,6 +663,17 @@ impl eframe::App for MyApp {
                     self.page = Page::Home;
                 };
 
+                if ui.button("Search").clicked() {
+                    self.page = Page::Search;
+                    // Picks up players from matches since the page was last open
+                    self.search.recent = None;
+                };
+
+                if ui.button("Matches").clicked() {
+                    self.page = Page::Matches;
+                    self.match_log.matches = None;
+                };
+
                 if self.auth.is_none() {
                     ui.add_enabled(false, egui::Button::new("Refresh"));
                 } else {
@@ -454,45 +697,68 @@ impl eframe::App for MyApp {
 
             ui.vertical(|ui| ui.add(egui::widgets::Separator::default().spacing(10.0)));
 
+            self.watch_lockfile(ctx);
+            self.poll_results();
 
             match &self.state {
 
                 State::Load => {
-                    self.image_promise = Some(Promise::spawn_thread("load_images", || {
+                    self.image_promise = Some(Promise::spawn_async(async {
                         let mut image_data = ImageData::new();
 
-                        if let Ok(_) = image_data.get_agents() {
-                            if let Ok(_) = image_data.get_maps() {
-                                return Some(image_data)
-                            }
+                        if image_data.load().await.is_ok() {
+                            return Some(image_data)
                         }
 
-                        return None
+                        None
                     }));
 
                     self.state = State::CheckPromise;
                 }
 
                 State::WaitValorant => {
-                    if self.settings.can_wait() {
-                        println!("Checking for Valorant");
-                        let mut loader = Loader::new();
-
-                        match loader.try_load() {
-                            Ok(_) => {
+                    if let Some(promise) = self.loader_promise.take() {
+                        match promise.try_take() {
+                            Ok(Ok(loader)) => {
                                 println!("Everything loaded successfully");
-                                self.auth = Some(Arc::new(loader));
+                                self.lockfile_watcher = loader.lockfile_path.as_ref().map(|found| LockfileWatcher::new(found.path.clone()));
+                                let auth = Arc::new(loader);
+                                self.riot = Some(RiotClient::new(Arc::clone(&auth)));
+                                self.auth = Some(auth);
+                                self.loader_error = None;
                                 self.state = State::Load;
                             }
-                            Err(err) => println!("Loader Error: {:?}", err)
+                            Ok(Err(err)) => {
+                                println!("Loader Error: {}", err);
+                                self.loader_error = Some(err);
+                            }
+                            Err(promise) => {
+                                self.loader_promise = Some(promise);
+                                ctx.request_repaint();
+                            }
                         }
-                    } else {
+                    } else if self.settings.can_wait() {
+                        println!("Checking for Valorant");
+                        let mut loader = Loader::new(self.settings.app_data_path(), self.settings.endpoints.with_env());
+
+                        self.loader_promise = Some(Promise::spawn_async(async move {
+                            loader.try_load().await.map(|_| loader)
+                        }));
+                    }
+
+                    if self.state == State::WaitValorant {
                         ui.add_space(ui.available_height() / 2.0 - 20.);
 
                         ui.horizontal(|ui| {
                             ui.add_space((ui.available_width() / 2.0) - 65.);
                             egui_twemoji::EmojiLabel::new("Looking for Valorant 👀").show(ui);
                         });
+
+                        if let Some(err) = &self.loader_error {
+                            ui.vertical_centered(|ui| {
+                                ui.colored_label(Color32::GRAY, err.to_string());
+                            });
+                        }
                     }
                 }
 
@@ -511,26 +777,30 @@ impl eframe::App for MyApp {
                             _ => {
                                 println!("Creating promise");
 
-                                if let Some(auth) = &self.auth {
+                                if let Some(riot) = &self.riot {
                                     // Cloned data to pass into promise
-                                    let new_auth = Arc::clone(auth);
+                                    let riot = riot.clone();
                                     let mut latest_match_id = String::new();
 
+                                    // Pre-game shares its match id with core-game, so only skip matches that already loaded
                                     if let Some(match_handler) = &self.current_match {
-                                        latest_match_id = match_handler.match_id.clone();
+                                        if match_handler.game_type == GameType::CoreGame {
+                                            latest_match_id = match_handler.match_id.clone();
+                                        }
                                     }
 
-                                    self.promise = Some(Promise::spawn_thread("look_for_match", move || {
-                                        // TODO: Implement pre-game
+                                    self.promise = Some(Promise::spawn_async(async move {
                                         let mut match_handler = MatchHandler::new();
 
-                                        if let Ok(_) = match_handler.get_match_id(Arc::clone(&new_auth)) {
-                                            if let Ok(_) = match_handler.get_match_details(Arc::clone(&new_auth), latest_match_id.clone()) {
-                                                return Some(match_handler)
+                                        match match_handler.get_match_id(&riot).await {
+                                            Ok(_) => match_handler.get_match_details(&riot, latest_match_id).await?,
+                                            Err(MatchError::MatchNotFound) => {
+                                                match_handler.get_pre_game(&riot).await.inspect_err(|err| println!("Pre-game error: {}", err))?;
                                             }
+                                            Err(err) => return Err(err),
                                         }
 
-                                        None
+                                        Ok(match_handler)
                                     }));
 
                                     self.settings.last_checked = 0;
@@ -543,23 +813,44 @@ impl eframe::App for MyApp {
                 }
 
                 State::CheckPromise => {
-                    if let Some(promise) = &self.promise {
-                        if let Some(promise) = promise.ready() {
-                            match promise {
-                                Some(match_handler) => {
-                                    println!("promise returned Some");
-                                    self.current_match = Some(match_handler.clone());
-                                    self.promise = None;
-                                }
-                                None => {
-                                    println!("promise returned None");
-                                    // returns none (so not exist?)
-                                    //self.loaded_players = None;
-                                    self.promise = None;
+                    if let Some(promise) = self.promise.take() {
+                        match promise.try_take() {
+                            Ok(Ok(match_handler)) => {
+                                println!("promise returned Some");
+
+                                // Only flash again once the match or who is flagged in it changes
+                                let flagged = (match_handler.match_id.clone(), match_handler.flagged_players().map(|x| x.uuid.clone()).collect::<Vec<String>>());
+
+                                if self.settings.flag_notifications && !flagged.1.is_empty() && self.notified_flags.as_ref() != Some(&flagged) {
+                                    ctx.send_viewport_cmd(egui::ViewportCommand::RequestUserAttention(egui::UserAttentionType::Critical));
                                 }
+
+                                self.notified_flags = Some(flagged);
+
+                                self.current_match = Some(match_handler);
+                                self.match_error = None;
+                                self.state = State::Refresh;
                             }
+                            Ok(Err(MatchError::AuthExpired)) => {
+                                println!("Tokens rejected, reloading");
+                                self.reload();
+                            }
+                            Ok(Err(err)) => {
+                                match err {
+                                    MatchError::MatchNotFound => {
+                                        self.match_error = None;
+                                        self.check_results();
+                                    }
+                                    MatchError::AlreadyLoaded => self.match_error = None,
+                                    err => {
+                                        println!("Match error: {}", err);
+                                        self.match_error = Some(err);
+                                    }
+                                }
 
-                            self.state = State::Refresh;
+                                self.state = State::Refresh;
+                            }
+                            Err(promise) => self.promise = Some(promise),
                         }
                     }
 
@@ -571,27 +862,22 @@ impl eframe::App for MyApp {
                         }
                     }
 
-                    if let Some(promise) = &self.image_promise {
-                        if let Some(promise) = promise.ready() {
-                            match promise {
-                                Some(image_data) => {
-                                    self.images = Some(image_data.to_owned());
-                                }
-                                None => {
-                                    println!("promise returned None");
-                                    // returns none (so not exist?)
-                                    //self.loaded_players = None;
-                                    self.promise = None;
-                                }
+                    // Taken once loaded so it can't move a reloaded app out of WaitValorant
+                    if let Some(promise) = self.image_promise.take() {
+                        match promise.try_take() {
+                            Ok(Some(image_data)) => self.images = Some(image_data),
+                            Ok(None) => println!("promise returned None"),
+                            Err(promise) => {
+                                self.image_promise = Some(promise);
+                                ui.label("Loading images...");
                             }
-
-                            self.state = State::Refresh;
-
-                        } else {
-                            self.state = State::CheckPromise;
-                            ui.label("Loading images...");
                         }
                     }
+
+                    // Nothing left to wait for, a reload already moved to WaitValorant
+                    if self.state == State::CheckPromise && self.promise.is_none() && self.image_promise.is_none() {
+                        self.state = State::Refresh;
+                    }
                 }
 
                 _ => {
@@ -602,7 +888,44 @@ impl eframe::App for MyApp {
             match &self.page {
                 Page::Settings => show_settings(self, ui),
                 Page::Home => self.home_page(ctx, ui),
+                Page::Search => show_search(self, ui),
+                Page::Matches => show_matches(self, ui),
+                Page::Player(_) => show_profile(self, ui),
             }
         });
+
+        // Saved once a change is finished rather than on every keystroke or drag step,
+        // eframe's autosave and exit save cover a field that stays focused
+        let editing = ctx.memory(|m| m.focused().is_some()) || ctx.input(|i| i.pointer.any_down());
+
+        if !editing && self.settings.changed_from(&self.saved_settings) {
+            if let Some(storage) = frame.storage_mut() {
+                self.save(storage);
+                storage.flush();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_older_settings() {
+        let settings: Settings = serde_json::from_str(r#"{"auto_refresh": false, "refresh_time": 0, "last_checked": 50}"#).unwrap();
+        let settings = settings.validated();
+
+        assert!(!settings.auto_refresh);
+        assert_eq!(settings.refresh_time, Settings::MIN_INTERVAL);
+        assert_eq!(settings.min_times_played, 3);
+        assert_eq!(settings.last_checked, 0);
+
+        let mut changed = settings.clone();
+        changed.last_checked = 100;
+        assert!(!changed.changed_from(&settings));
+
+        changed.show_all = true;
+        assert!(changed.changed_from(&settings));
     }
 }
@@ -1,10 +1,14 @@
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::fmt;
 use std::time::{SystemTime, UNIX_EPOCH};
-use reqwest::blocking::Client;
+use reqwest::StatusCode;
+use tokio::task::JoinSet;
 use crate::{LoadedPlayer, name_service, TeamType};
 use crate::database;
-use crate::database::{MatchHistory, NameHistory};
-use crate::loader::Loader;
+use crate::database::{DbError, Match, MatchSnapshot, NameHistory, Participant, PlayerFlag, PlayerMatch, PlayerNote, EncounterStats};
+use crate::pre_game;
+use crate::api::PvpApi;
+use crate::riot_client::RiotError;
 
 #[derive(serde::Deserialize, Debug, Default)]
 pub struct CurrentGamePlayer {
@@ -26,6 +30,14 @@ pub struct CurrentGameMatch {
     // pub connection_details: ConnectionDetails,
     #[serde(rename = "Players")]
     pub players: Vec<Player>,
+    #[serde(rename = "MatchmakingData", default)]
+    pub matchmaking_data: Option<MatchmakingData>,
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct MatchmakingData {
+    #[serde(rename = "QueueID")]
+    pub queue_id: String,
 }
 
 #[derive(serde::Deserialize, Debug, Default)]
@@ -54,186 +66,416 @@ pub struct PlayerIdentity {
     pub hide_level: bool,
 }
 
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum GameType {
+    #[default]
+    CoreGame,
+    PreGame,
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct MatchHandler {
-    client: Client,
     pub match_id: String,
-    pub game_type: String,
+    pub game_type: GameType,
     pub map_path: String,
     pub game_mode: String,
+    pub queue: String,
     pub server: String,
     pub players: Vec<LoadedPlayer>,
 }
 
+#[derive(Debug)]
 pub enum MatchError {
-    MatchNotFound, Other
+    // 404, the player isn't in a match
+    MatchNotFound,
+    // The match is the one already shown
+    AlreadyLoaded,
+    // 400 or 401 even after refreshing the tokens
+    AuthExpired,
+    // Still 429 after retrying
+    RateLimited,
+    // 5xx, Riot's servers are having trouble
+    RiotDown(StatusCode),
+    Other(RiotError),
+}
+
+impl fmt::Display for MatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatchError::MatchNotFound => write!(f, "not in a match"),
+            MatchError::AlreadyLoaded => write!(f, "match already loaded"),
+            MatchError::AuthExpired => write!(f, "Riot rejected the login, reconnecting"),
+            MatchError::RateLimited => write!(f, "Rate limited by Riot, trying again later"),
+            MatchError::RiotDown(status) => write!(f, "Riot servers are unavailable ({})", status),
+            MatchError::Other(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for MatchError {}
+
+impl From<RiotError> for MatchError {
+    fn from(err: RiotError) -> Self {
+        match err {
+            RiotError::Status(StatusCode::NOT_FOUND) => MatchError::MatchNotFound,
+            RiotError::Status(StatusCode::BAD_REQUEST | StatusCode::UNAUTHORIZED) => MatchError::AuthExpired,
+            RiotError::Status(StatusCode::TOO_MANY_REQUESTS) => MatchError::RateLimited,
+            RiotError::Status(status) if status.is_server_error() => MatchError::RiotDown(status),
+            err => MatchError::Other(err),
+        }
+    }
+}
+
+impl From<pre_game::Error> for MatchError {
+    fn from(err: pre_game::Error) -> Self {
+        match err {
+            pre_game::Error::NotPreGame => MatchError::MatchNotFound,
+            pre_game::Error::Riot(err) | pre_game::Error::NameService(err) => MatchError::from(err),
+        }
+    }
+}
+
+// Game pods look like "aresriot.aws-rclusterprod-euc1-1.eu-gp-frankfurt-1", the server is the second to last part
+fn server_from_pod(game_pod: &str) -> String {
+    let parts: Vec<&str> = game_pod.split('-').collect();
+
+    parts.len()
+        .checked_sub(2)
+        .and_then(|i| parts.get(i))
+        .unwrap_or(&"")
+        .to_string()
 }
 
 impl MatchHandler {
     pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn get_match_id(&mut self, api: &impl PvpApi) -> Result<(), MatchError> {
+        self.match_id = api.core_game_player().await?.match_id;
+
+        Ok(())
+    }
+
+    pub async fn get_match_details(&mut self, api: &impl PvpApi, latest_match_id: String) -> Result<(), MatchError> {
+        let json = api.core_game_match(&self.match_id).await?;
+
+        self.match_id = json.match_id.clone();
+
+        if self.match_id == latest_match_id {
+            return Err(MatchError::AlreadyLoaded)
+        }
+
+        println!("passed match check");
+
+        self.map_path = json.map_id.clone();
+        self.game_mode = json.gamemode_id.clone();
+
+        self.queue = json.matchmaking_data.as_ref().map(|data| data.queue_id.clone()).unwrap_or_default();
+        self.server = server_from_pod(&json.game_pod);
+
+        let player_ids: Vec<String> = json.players.iter().map(|player| player.player_identity.uuid.clone()).collect();
+        let time_now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+
+        // History is read before anything from this match is written
+        let (player_names, mut histories) = tokio::join!(
+            name_service::NameService::get_names(api, player_ids.clone()),
+            load_histories(player_ids, time_now),
+        );
+
+        let player_names = player_names?;
+        let mut players = Vec::new();
+        let mut participants = Vec::new();
+        let Some(player_team) = json.players.iter().find(|x| x.player_identity.uuid == api.puuid()).map(|x| x.team_id.clone()) else {
+            return Err(MatchError::Other(RiotError::Incomplete("our own player")))
+        };
+
+        for player in json.players.iter() {
+            // Players the name service left out are skipped like in pre-game
+            let Some(name) = player_names.iter().find(|x| x.uuid == player.player_identity.uuid) else {
+                continue
+            };
+            let history = histories.remove(&name.uuid).unwrap_or_else(|| PlayerHistory::new(time_now));
+
+            participants.push(Participant {
+                uuid: name.uuid.clone(),
+                name: name.game_name.clone(),
+                tag: name.tag_line.clone(),
+                agent_id: player.agent_id.clone(),
+                enemy: player.team_id != player_team,
+            });
+
+            players.push(LoadedPlayer {
+                uuid: name.uuid.clone(),
+                name: name.game_name.clone(),
+                tag: name.tag_line.clone(),
+                team: if player.team_id == player_team { TeamType::Ally } else { TeamType::Enemy },
+
+                match_history: history.match_history,
+                name_history: history.name_history,
+
+                times_played: history.times_played,
+                last_played: history.last_played,
+                note: history.note,
+                flag: history.flag,
+                flag_reason: history.flag_reason,
+                encounters: history.encounters,
+
+                agent_id: player.agent_id.clone(),
+                incognito: player.player_identity.incognito,
+            });
+        }
+
+        let snapshot = MatchSnapshot {
+            details: Match {
+                match_id: json.match_id.clone(),
+                map_id: json.map_id.clone(),
+                gamemode_id: json.gamemode_id.clone(),
+                queue_id: self.queue.clone(),
+                server: self.server.clone(),
+                game_pod: json.game_pod.clone(),
+                start_time: time_now,
+                puuid: api.puuid().to_string(),
+                ..Default::default()
+            },
+            participants,
+        };
+
+        match tokio::task::spawn_blocking(move || database::record_match(snapshot)).await {
+            Ok(Ok(_)) => println!("Recorded match successfully"),
+            Ok(Err(err)) => println!("Failed to record match: {}", err),
+            Err(err) => println!("Failed to record match: {}", err),
+        }
+
+        self.players = players;
+
+        Ok(())
+    }
+
+    // Players the user flagged, shown as alerts when the match loads
+    pub fn flagged_players(&self) -> impl Iterator<Item = &LoadedPlayer> {
+        self.players.iter().filter(|x| x.flag.is_some())
+    }
+
+    pub async fn get_pre_game(&mut self, api: &impl PvpApi) -> Result<(), pre_game::Error> {
+        let match_id = api.pre_game_player().await?.match_id;
+        let pre_game = api.pre_game_match(&match_id).await?;
+
+        self.players = pre_game.get_players(api).await?;
+
+        self.match_id = pre_game.match_id.clone();
+        self.game_type = GameType::PreGame;
+        self.map_path = pre_game.map_id.clone();
+        self.game_mode = pre_game.gamemode_id.clone();
+        self.server = server_from_pod(&pre_game.game_pod);
+
+        Ok(())
+    }
+}
+
+// What the database knew about a player before the current match
+#[derive(Debug, Default)]
+pub struct PlayerHistory {
+    pub match_history: Vec<PlayerMatch>,
+    pub name_history: Vec<NameHistory>,
+    pub times_played: i64,
+    pub last_played: i64,
+    pub note: Option<PlayerNote>,
+    pub flag: Option<PlayerFlag>,
+    pub flag_reason: String,
+    pub encounters: EncounterStats,
+}
+
+impl PlayerHistory {
+    pub fn new(time_now: i64) -> Self {
         Self {
-            client: Client::builder()
-                .danger_accept_invalid_certs(true)
-                .build()
-                .unwrap(),
+            last_played: time_now,
             ..Default::default()
         }
     }
 
-    pub fn get_match_id(&mut self, auth: Arc<Loader>) -> Result<(), MatchError> {
-        return match self.client.get(format!("https://glz-{}-1.{}.a.pvp.net/core-game/v1/players/{}", auth.region, auth.shard, auth.puuid))
-            .bearer_auth(&auth.access_token)
-            .header("X-Riot-Entitlements-JWT", &auth.token)
-            .header("X-Riot-ClientPlatform", "ew0KCSJwbGF0Zm9ybVR5cGUiOiAiUEMiLA0KCSJwbGF0Zm9ybU9TIjogIldpbmRvd3MiLA0KCSJwbGF0Zm9ybU9TVmVyc2lvbiI6ICIxMC4wLjE5MDQyLjEuMjU2LjY0Yml0IiwNCgkicGxhdGZvcm1DaGlwc2V0IjogIlVua25vd24iDQp9")
-            .header("X-Riot-ClientVersion", &auth.client_version)
-            .send()
-        {
-            Ok(res) => {
-
-                println!("Match id stats: {:?}", res.status());
-
-                if res.status().is_success() {
-                    match res.json::<CurrentGamePlayer>() {
-                        Ok(json) => {
-                            self.match_id = json.match_id.clone();
-                            Ok(())
-                        },
-                        Err(_) => Err(MatchError::Other),
-                    }
-                } else {
-
-                    // stop display users, show waiting for match
-
-                    Err(MatchError::MatchNotFound)
-                }
-            },
-            Err(_) => Err(MatchError::Other),
+    pub fn load(uuid: String, time_now: i64) -> Self {
+        let mut history = Self::new(time_now);
+
+        match database::get_user_name_history(uuid.clone()) {
+            Ok(name_his) => history.name_history = name_his,
+            Err(err) => println!("Couldnt get name history: {}", err),
         }
-    }
 
-    pub fn get_match_details(&mut self, auth: Arc<Loader>, latest_match_id: String) -> Result<(), ()> {
-        return match self.client.get(format!("https://glz-{}-1.{}.a.pvp.net/core-game/v1/matches/{}", auth.region, auth.shard, self.match_id))
-            .bearer_auth(&auth.access_token)
-            .header("X-Riot-Entitlements-JWT", &auth.token)
-            .header("X-Riot-ClientPlatform", "ew0KCSJwbGF0Zm9ybVR5cGUiOiAiUEMiLA0KCSJwbGF0Zm9ybU9TIjogIldpbmRvd3MiLA0KCSJwbGF0Zm9ybU9TVmVyc2lvbiI6ICIxMC4wLjE5MDQyLjEuMjU2LjY0Yml0IiwNCgkicGxhdGZvcm1DaGlwc2V0IjogIlVua25vd24iDQp9")
-            .header("X-Riot-ClientVersion", &auth.client_version)
-            .send()
-        {
-            Ok(res) => {
+        match database::get_user_match_history(uuid.clone()) {
+            Ok(match_his) => history.match_history = match_his,
+            Err(err) => println!("Couldnt get match history: {}", err),
+        }
 
-                println!("Current game match status: {}", res.status());
+        match database::get_note(uuid.clone()) {
+            Ok(note) => history.note = Some(note),
+            Err(DbError::NotFound) => {}
+            Err(err) => println!("Couldnt get note: {}", err),
+        }
 
-                if res.status().is_success() {
-                    match res.json::<CurrentGameMatch>() {
-                        Ok(json) => {
-                            self.match_id = json.match_id.clone();
+        match database::encounter_stats(uuid.clone()) {
+            Ok(encounters) => history.encounters = encounters,
+            Err(err) => println!("Couldnt get encounter stats: {}", err),
+        }
 
-                            if self.match_id == latest_match_id {
-                                return Err(())
-                            }
-
-                            println!("passed match check");
-
-                            //println!("{:?}", json);
-
-                            self.map_path = json.map_id.clone();
-                            self.game_mode = json.gamemode_id.clone();
-
-                            // get server
-                            let mut server = String::new();
-
-                            let parts: Vec<&str> = json.game_pod.split('-').collect();
-                            let result = parts.get(parts.len() - 2).unwrap_or(&"");
-
-                            self.server = result.to_string();
-
-                            let player_ids: Vec<String> = json.players.iter().map(|player| player.player_identity.uuid.clone()).collect();
-                            let time_now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
-
-                            if let Some(player_names) = name_service::NameService::get_names(&auth, player_ids) {
-                                let mut players = Vec::new();
-                                let player_team = json.players.iter().find(|x| x.player_identity.uuid == auth.puuid).unwrap().team_id.clone();
-
-                                for (i, player) in json.players.iter().enumerate() {
-                                    let name = player_names.iter().find(|x| x.uuid == player.player_identity.uuid).unwrap();
-
-                                    let mut times_played: i64 = 0;
-                                    let mut last_played: i64 = time_now;
-                                    let mut match_history: Vec<MatchHistory> = Vec::new();
-                                    let mut name_history: Vec<NameHistory> = Vec::new();
-
-                                    if let Ok(name_his) = database::get_user_name_history(name.uuid.clone()) {
-                                        name_history = name_his;
-                                    } else {
-                                        println!("Couldnt get name history")
-                                    }
-
-                                    if let Ok(match_his) = database::get_user_match_history(name.uuid.clone()) {
-                                        match_history = match_his;
-                                    } else {
-                                        println!("Couldnt get match history")
-                                    }
-
-                                    if let Ok(user) = database::get_user(name.uuid.clone()) {
-                                        times_played = user.times_played;
-                                        last_played = user.last_played;
-                                    } else {
-                                        println!("Couldnt get user")
-                                    }
-
-                                    if let Ok(_) = database::update_user(name.uuid.clone()) {
-                                        println!("Updated new successfully")
-                                    } else {
-                                        println!("Unable to update user")
-                                    }
-
-                                    if let Ok(_) = database::add_new_name(name.uuid.clone(), name.game_name.clone(), name.tag_line.clone()) {
-                                        println!("Added new name successfully")
-                                    } else {
-                                        println!("Failed to add new name")
-                                    }
-
-                                    if let Ok(_) = database::add_new_match(name.uuid.clone(), json.match_id.clone(), json.map_id.clone(), json.gamemode_id.clone(), player.agent_id.clone(), !(player.team_id == player_team), time_now) {
-                                        println!("Added new match successfully")
-                                    } else {
-                                        println!("Failed to add new match")
-                                    }
-
-                                    let player_data = json.players.iter().find(|x| x.player_identity.uuid == name.uuid).unwrap();
-
-                                    players.push(LoadedPlayer {
-                                        uuid: name.uuid.clone(),
-                                        name: name.game_name.clone(),
-                                        tag: name.tag_line.clone(),
-                                        team: if json.players.get(i).unwrap().team_id == "Blue" { TeamType::Ally } else { TeamType::Enemy },
-
-                                        match_history: match_history.clone(),
-                                        name_history: name_history.clone(),
-
-                                        times_played,
-                                        last_played,
-
-                                        agent_id: player_data.agent_id.clone(),
-                                        incognito: player_data.player_identity.incognito.clone(),
-                                    });
-                                }
-
-                                self.players = players.clone();
+        match database::get_user(uuid) {
+            Ok(user) => {
+                history.times_played = user.times_played;
+                history.last_played = user.last_played;
+                history.flag = user.flag();
+                history.flag_reason = user.flag_reason;
+            }
+            // First time seeing this player
+            Err(DbError::NotFound) => {}
+            Err(err) => println!("Couldnt get user: {}", err),
+        }
 
-                            }
-
-                            Ok(())
-                        },
-                        Err(_) => Err(()),
-                    }
-                } else {
-                    Err(())
-                }
-            },
-            Err(err) => {
-                println!("Here {:?}", err);
-                Err(())
-            },
+        history
+    }
+}
+
+// Looks up every player at once on the blocking pool, keyed by uuid
+pub async fn load_histories(uuids: Vec<String>, time_now: i64) -> HashMap<String, PlayerHistory> {
+    let mut lookups = JoinSet::new();
+
+    for uuid in uuids {
+        lookups.spawn_blocking(move || {
+            let history = PlayerHistory::load(uuid.clone(), time_now);
+            (uuid, history)
+        });
+    }
+
+    let mut histories = HashMap::new();
+
+    while let Some(lookup) = lookups.join_next().await {
+        match lookup {
+            Ok((uuid, history)) => {
+                histories.insert(uuid, history);
+            }
+            Err(err) => println!("History lookup failed: {}", err),
         }
     }
-}
\ No newline at end of file
+
+    histories
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::fixtures::{FixturePvpApi, MATCH_ID, PUUID};
+
+    #[tokio::test]
+    async fn loads_core_game_match() {
+        let _db = database::use_test_db();
+
+        let api = FixturePvpApi { in_core_game: true, ..Default::default() };
+        let mut match_handler = MatchHandler::new();
+
+        assert!(match_handler.get_match_id(&api).await.is_ok());
+        assert_eq!(match_handler.match_id, MATCH_ID);
+
+        assert!(match_handler.get_match_details(&api, String::new()).await.is_ok());
+        assert_eq!(match_handler.game_type, GameType::CoreGame);
+        assert_eq!(match_handler.server, "frankfurt");
+        assert_eq!(match_handler.map_path, "/Game/Maps/Ascent/Ascent");
+        assert_eq!(match_handler.players.len(), 4);
+
+        let enemy = match_handler.players.iter().find(|x| x.name == "jett diff").unwrap();
+        assert_eq!(enemy.tag, "0001");
+        assert!(matches!(enemy.team, TeamType::Enemy));
+
+        let incognito = match_handler.players.iter().filter(|x| x.incognito).count();
+        assert_eq!(incognito, 1);
+
+        // The match was recorded for every player, with the enemy team flagged
+        let history = database::get_user_match_history(enemy.uuid.clone()).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].participant.match_id, MATCH_ID);
+        assert_eq!(history[0].participant.enemy, Some(true));
+        assert_eq!(history[0].details.map_id, "/Game/Maps/Ascent/Ascent");
+
+        let own = database::get_user_match_history(PUUID.to_string()).unwrap();
+        assert_eq!(own[0].participant.enemy, Some(false));
+
+        // Match level data is stored once
+        let record = turbosql::select!(Match "WHERE match_id =" MATCH_ID).unwrap();
+        assert_eq!(record.queue_id, "competitive");
+        assert_eq!(record.server, "frankfurt");
+        assert_eq!(record.puuid, PUUID);
+
+        // Same match again is skipped
+        let mut match_handler = MatchHandler::new();
+        match_handler.get_match_id(&api).await.ok();
+        assert!(matches!(match_handler.get_match_details(&api, MATCH_ID.to_string()).await, Err(MatchError::AlreadyLoaded)));
+    }
+
+    #[test]
+    fn classifies_riot_errors() {
+        let classify = |status| MatchError::from(RiotError::Status(status));
+
+        assert!(matches!(classify(StatusCode::NOT_FOUND), MatchError::MatchNotFound));
+        assert!(matches!(classify(StatusCode::BAD_REQUEST), MatchError::AuthExpired));
+        assert!(matches!(classify(StatusCode::UNAUTHORIZED), MatchError::AuthExpired));
+        assert!(matches!(classify(StatusCode::TOO_MANY_REQUESTS), MatchError::RateLimited));
+        assert!(matches!(classify(StatusCode::BAD_GATEWAY), MatchError::RiotDown(StatusCode::BAD_GATEWAY)));
+        assert!(matches!(classify(StatusCode::FORBIDDEN), MatchError::Other(_)));
+
+        assert!(matches!(MatchError::from(pre_game::Error::NotPreGame), MatchError::MatchNotFound));
+        assert!(matches!(MatchError::from(pre_game::Error::Riot(RiotError::Status(StatusCode::TOO_MANY_REQUESTS))), MatchError::RateLimited));
+        assert!(matches!(MatchError::from(pre_game::Error::NameService(RiotError::Status(StatusCode::UNAUTHORIZED))), MatchError::AuthExpired));
+    }
+
+    #[tokio::test]
+    async fn not_in_game() {
+        let api = FixturePvpApi::default();
+        let mut match_handler = MatchHandler::new();
+
+        assert!(matches!(match_handler.get_match_id(&api).await, Err(MatchError::MatchNotFound)));
+        assert!(matches!(match_handler.get_pre_game(&api).await, Err(pre_game::Error::NotPreGame)));
+    }
+
+    #[tokio::test]
+    async fn loads_pre_game_allies() {
+        let _db = database::use_test_db();
+
+        let api = FixturePvpApi { in_pre_game: true, ..Default::default() };
+        let mut match_handler = MatchHandler::new();
+
+        assert!(matches!(match_handler.get_match_id(&api).await, Err(MatchError::MatchNotFound)));
+        assert!(match_handler.get_pre_game(&api).await.is_ok());
+
+        assert_eq!(match_handler.game_type, GameType::PreGame);
+        assert_eq!(match_handler.match_id, MATCH_ID);
+        assert_eq!(match_handler.players.len(), 2);
+        assert!(match_handler.players.iter().all(|x| matches!(x.team, TeamType::Ally)));
+
+        let ally = match_handler.players.iter().find(|x| x.uuid != PUUID).unwrap();
+        assert_eq!(ally.name, "Sova Main");
+        assert_eq!(ally.agent_id, "");
+    }
+
+    #[tokio::test]
+    async fn alerts_on_flagged_players() {
+        let _db = database::use_test_db();
+
+        // With the teams swapped the player is on Red, so Blue is the enemy team
+        let jett = "c7d8e9f0-1a2b-5c3d-8e4f-5a6b7c8d9e0f".to_string();
+        database::set_flag(jett.clone(), Some(PlayerFlag::Avoid), "Throws on attack".to_string()).unwrap();
+
+        let api = FixturePvpApi { in_core_game: true, on_red_team: true, ..Default::default() };
+        let mut match_handler = MatchHandler::new();
+
+        match_handler.get_match_id(&api).await.unwrap();
+        match_handler.get_match_details(&api, String::new()).await.unwrap();
+
+        let own = match_handler.players.iter().find(|x| x.uuid == PUUID).unwrap();
+        assert!(matches!(own.team, TeamType::Ally));
+
+        let flagged: Vec<&LoadedPlayer> = match_handler.flagged_players().collect();
+        assert_eq!(flagged.len(), 1);
+        assert_eq!(flagged[0].uuid, jett);
+        assert!(matches!(flagged[0].team, TeamType::Enemy));
+        assert_eq!(flagged[0].flag, Some(PlayerFlag::Avoid));
+        assert_eq!(flagged[0].flag_reason, "Throws on attack");
+    }
+}
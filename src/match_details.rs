@@ -0,0 +1,244 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use reqwest::StatusCode;
+use crate::api::PvpApi;
+use crate::database;
+use crate::database::{MatchOutcome, MatchPlayerStats, MatchResult};
+use crate::riot_client::RiotError;
+
+// Only matches loaded this recently are looked up, older ones without results were most likely remade
+const PENDING_WINDOW: i64 = 86400;
+const PENDING_LIMIT: i64 = 3;
+
+// Details can take a little while to be published after the match ends
+const ATTEMPTS: u32 = 4;
+const RETRY_DELAY: Duration = Duration::from_secs(15);
+
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct MatchDetails {
+    #[serde(rename = "matchInfo")]
+    pub match_info: MatchInfo,
+    #[serde(default)]
+    pub players: Vec<Player>,
+    // Missing for modes without teams
+    #[serde(default)]
+    pub teams: Option<Vec<Team>>,
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct MatchInfo {
+    #[serde(rename = "matchId")]
+    pub match_id: String,
+    #[serde(rename = "isCompleted", default)]
+    pub is_completed: bool,
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct Player {
+    #[serde(rename = "subject")]
+    pub uuid: String,
+    #[serde(rename = "teamId")]
+    pub team_id: String,
+    // Null for players that left before the end
+    #[serde(default)]
+    pub stats: Option<Stats>,
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct Stats {
+    pub score: i64,
+    pub kills: i64,
+    pub deaths: i64,
+    pub assists: i64,
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct Team {
+    #[serde(rename = "teamId")]
+    pub team_id: String,
+    pub won: bool,
+    #[serde(rename = "roundsWon")]
+    pub rounds_won: i64,
+    #[serde(rename = "roundsPlayed")]
+    pub rounds_played: i64,
+}
+
+impl MatchDetails {
+    pub fn outcome(&self) -> MatchOutcome {
+        let match_id = self.match_info.match_id.clone();
+
+        let teams = self.teams.iter().flatten().map(|team| MatchResult {
+            match_id: match_id.clone(),
+            team_id: team.team_id.clone(),
+            won: team.won,
+            rounds_won: team.rounds_won,
+            rounds_played: team.rounds_played,
+            ..Default::default()
+        }).collect();
+
+        let players = self.players.iter().map(|player| {
+            let stats = player.stats.as_ref();
+
+            MatchPlayerStats {
+                match_id: match_id.clone(),
+                uuid: player.uuid.clone(),
+                team_id: player.team_id.clone(),
+                kills: stats.map_or(0, |x| x.kills),
+                deaths: stats.map_or(0, |x| x.deaths),
+                assists: stats.map_or(0, |x| x.assists),
+                score: stats.map_or(0, |x| x.score),
+                ..Default::default()
+            }
+        }).collect();
+
+        MatchOutcome { match_id, teams, players }
+    }
+}
+
+// Looks up the results of recently loaded matches that have ended, returns how many were recorded
+pub async fn record_pending(api: &impl PvpApi) -> Result<usize, RiotError> {
+    let puuid = api.puuid().to_string();
+    let since = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64 - PENDING_WINDOW;
+
+    let pending = match tokio::task::spawn_blocking(move || database::pending_results(&puuid, since, PENDING_LIMIT)).await {
+        Ok(Ok(pending)) => pending,
+        Ok(Err(err)) => {
+            println!("Couldnt get pending matches: {}", err);
+            return Ok(0)
+        }
+        Err(err) => {
+            println!("Couldnt get pending matches: {}", err);
+            return Ok(0)
+        }
+    };
+
+    let mut recorded = 0;
+
+    for match_id in pending {
+        // A match that can't be looked up is counted and skipped so the others still get recorded
+        let details = match fetch(api, &match_id).await {
+            Ok(Some(details)) => details,
+            Ok(None) => {
+                println!("Match {} still isn't finished", match_id);
+                lookup_failed(match_id).await;
+                continue
+            }
+            Err(err) => {
+                println!("Couldnt get results of {}: {}", match_id, err);
+                lookup_failed(match_id).await;
+                continue
+            }
+        };
+
+        match tokio::task::spawn_blocking(move || database::record_results(details.outcome())).await {
+            Ok(Ok(_)) => recorded += 1,
+            Ok(Err(err)) => println!("Failed to record match results: {}", err),
+            Err(err) => println!("Failed to record match results: {}", err),
+        }
+    }
+
+    Ok(recorded)
+}
+
+async fn lookup_failed(match_id: String) {
+    match tokio::task::spawn_blocking(move || database::result_lookup_failed(&match_id)).await {
+        Ok(Ok(_)) => {}
+        Ok(Err(err)) => println!("Failed to count result lookup: {}", err),
+        Err(err) => println!("Failed to count result lookup: {}", err),
+    }
+}
+
+// None when the match still isn't finished after a few tries
+async fn fetch(api: &impl PvpApi, match_id: &str) -> Result<Option<MatchDetails>, RiotError> {
+    for attempt in 1..=ATTEMPTS {
+        match api.match_details(match_id).await {
+            Ok(details) if details.match_info.is_completed => return Ok(Some(details)),
+            Ok(_) | Err(RiotError::Status(StatusCode::NOT_FOUND)) => {}
+            Err(err) => return Err(err),
+        }
+
+        if attempt < ATTEMPTS {
+            tokio::time::sleep(RETRY_DELAY).await;
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::fixtures::{FixturePvpApi, MATCH_DETAILS, MATCH_ID, PUUID};
+    use crate::database::Match;
+    use crate::r#match::MatchHandler;
+
+    #[test]
+    fn converts_to_outcome() {
+        let details: MatchDetails = serde_json::from_str(MATCH_DETAILS).unwrap();
+        let outcome = details.outcome();
+
+        assert_eq!(outcome.match_id, MATCH_ID);
+        assert_eq!(outcome.teams.len(), 2);
+
+        let blue = outcome.teams.iter().find(|x| x.team_id == "Blue").unwrap();
+        assert!(blue.won);
+        assert_eq!((blue.rounds_won, blue.rounds_played), (13, 22));
+
+        let jett = outcome.players.iter().find(|x| x.uuid.starts_with("c7d8")).unwrap();
+        assert_eq!((jett.kills, jett.deaths, jett.assists, jett.score), (18, 16, 2, 4890));
+
+        // A player without stats is still recorded on their team
+        let left = outcome.players.iter().find(|x| x.uuid.starts_with("d1e2")).unwrap();
+        assert_eq!((left.team_id.as_str(), left.kills), ("Red", 0));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn records_finished_matches() {
+        let _db = database::use_test_db();
+
+        let api = FixturePvpApi { in_core_game: true, ..Default::default() };
+        let mut match_handler = MatchHandler::new();
+        match_handler.get_match_id(&api).await.unwrap();
+        match_handler.get_match_details(&api, String::new()).await.unwrap();
+
+        // Still in progress, nothing to record yet
+        assert_eq!(record_pending(&api).await.unwrap(), 0);
+
+        let api = FixturePvpApi { match_finished: true, ..Default::default() };
+        record_pending(&api).await.unwrap();
+
+        let (jett, sova) = ("c7d8e9f0-1a2b-5c3d-8e4f-5a6b7c8d9e0f", "b6a1e2f3-4d5c-5b6a-8f7e-1d2c3b4a5f6e");
+
+        let record = tokio::task::spawn_blocking(move || database::encounter_stats(jett.to_string())).await.unwrap().unwrap().win_loss;
+        assert_eq!((record.against_wins, record.against_losses), (1, 0));
+
+        let record = tokio::task::spawn_blocking(move || database::encounter_stats(sova.to_string())).await.unwrap().unwrap().win_loss;
+        assert_eq!((record.with_wins, record.with_losses), (1, 0));
+
+        // Already recorded matches aren't looked up again
+        assert_eq!(record_pending(&api).await.unwrap(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn skips_failing_matches() {
+        let _db = database::use_test_db();
+
+        let time_now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+
+        // The broken match is newer, so it is looked up first
+        for (match_id, start_time) in [(MATCH_ID, time_now - 60), ("broken-match", time_now)] {
+            let details = Match { match_id: match_id.to_string(), puuid: PUUID.to_string(), start_time, ..Default::default() };
+            tokio::task::spawn_blocking(move || database::add_match(details)).await.unwrap().unwrap();
+        }
+
+        let api = FixturePvpApi { match_finished: true, failing_match: Some("broken-match"), ..Default::default() };
+        assert_eq!(record_pending(&api).await.unwrap(), 1);
+
+        let pending = || tokio::task::spawn_blocking(move || database::pending_results(PUUID, 0, 10));
+        assert_eq!(pending().await.unwrap().unwrap(), vec!["broken-match".to_string()]);
+
+        // Gives up on it after a few runs
+        record_pending(&api).await.unwrap();
+        record_pending(&api).await.unwrap();
+        assert!(pending().await.unwrap().unwrap().is_empty());
+    }
+}
@@ -1,5 +1,6 @@
 use serde::Deserialize;
-use crate::loader::Loader;
+use crate::api::PvpApi;
+use crate::riot_client::RiotError;
 
 #[derive(Deserialize, Debug)]
 pub struct NameService {
@@ -12,33 +13,7 @@ pub struct NameService {
 }
 
 impl NameService {
-    pub fn get_names(auth: &Loader, users: Vec<String>) -> Option<Vec<NameService>> {
-        let client = match reqwest::blocking::Client::builder()
-            .danger_accept_invalid_certs(true)
-            .build() {
-            Ok(client) => client,
-            Err(_) => return None,
-        };
-
-        return match client.put(format!("https://pd.{}.a.pvp.net/name-service/v2/players", &auth.shard))
-            .bearer_auth(&auth.access_token)
-            .header("X-Riot-Entitlements-JWT", &auth.token)
-            .header("X-Riot-ClientPlatform", "ew0KCSJwbGF0Zm9ybVR5cGUiOiAiUEMiLA0KCSJwbGF0Zm9ybU9TIjogIldpbmRvd3MiLA0KCSJwbGF0Zm9ybU9TVmVyc2lvbiI6ICIxMC4wLjE5MDQyLjEuMjU2LjY0Yml0IiwNCgkicGxhdGZvcm1DaGlwc2V0IjogIlVua25vd24iDQp9")
-            .header("X-Riot-ClientVersion", &auth.client_version)
-            .body(serde_json::to_string(&users).unwrap())
-            .send()
-        {
-            Ok(res) => {
-                if res.status().is_success() {
-                    match res.json::<Vec<NameService>>() {
-                        Ok(json) => Some(json),
-                        Err(_) => None,
-                    }
-                } else {
-                    None
-                }
-            },
-            Err(_) => None,
-        }
+    pub async fn get_names(api: &impl PvpApi, users: Vec<String>) -> Result<Vec<NameService>, RiotError> {
+        api.names(&users).await.inspect_err(|err| println!("Name service error: {}", err))
     }
 }
@@ -1,150 +1,123 @@
-// use crate::{LoadedPlayer, name_service, TeamType};
-//
-// #[derive(serde::Deserialize, Debug, Default)]
-// pub struct PreGameId {
-//     #[serde(rename = "MatchID")]
-//     pub match_id: String,
-// }
-//
-// #[derive(serde::Deserialize, Debug)]
-// pub struct PreGame {
-//     #[serde(rename = "AllyTeam")]
-//     pub ally_team: Team,
-// }
-//
-// #[derive(serde::Deserialize, Debug)]
-// pub struct Team {
-//     #[serde(rename = "Players")]
-//      pub players: Vec<Player>
-// }
-//
-// #[derive(serde::Deserialize, Debug)]
-// pub struct Player {
-//     #[serde(rename = "Subject")]
-//     pub uuid: String,
-//     #[serde(rename = "CharacterID")]
-//     pub agent_id: String,
-//     #[serde(rename = "CharacterSelectionState")]
-//     pub selection_state: String,
-//     #[serde(rename = "CompetitiveTier")]
-//     pub rank: u8,
-//     #[serde(rename = "PlayerIdentity")]
-//     pub player_identity: PlayerIdentity
-// }
-//
-// #[derive(serde::Deserialize, Debug)]
-// pub struct PlayerIdentity {
-//     #[serde(rename = "AccountLevel")]
-//     pub level: u16,
-//     #[serde(rename = "Incognito")]
-//     pub incognito: bool,
-//     #[serde(rename = "HideAccountLevel")]
-//     pub hide_level: bool,
-// }
-//
-// #[derive(Debug)]
-// pub enum Error { NotPreGame, ClientError, RiotError, Unknown }
-//
-// impl PreGame {
-//     pub fn get_match_id(auth: &Loader) -> Result<String, Error> {
-//         let client = match reqwest::blocking::Client::builder()
-//             .danger_accept_invalid_certs(true)
-//             .build() {
-//                 Ok(client) => client,
-//                 Err(_) => return Err(Error::ClientError),
-//             };
-//
-//         let resp = match client.get(format!("https://glz-{}-1.{}.a.pvp.net/pregame/v1/players/{}", auth.region, auth.shard, auth.puuid))
-//             .bearer_auth(&auth.access_token)
-//             .header("X-Riot-Entitlements-JWT", &auth.token)
-//             .send() {
-//                 Ok(resp) => resp,
-//                 Err(err) => {
-//                     println!("{:?}", err);
-//                     return Err(Error::RiotError)
-//                 }
-//             };
-//
-//
-//
-//         if resp.status().is_success() {
-//             return if let Ok(pre_game_id) = resp.json::<PreGameId>() {
-//                 Ok(pre_game_id.match_id.clone())
-//             } else {
-//                 Err(Error::NotPreGame)
-//             }
-//         }
-//
-//         return Err(Error::Unknown)
-//     }
-//
-//     pub fn get_pre_game(auth: &Loader, match_id: String) -> Result<PreGame, Error>{
-//         let client = match reqwest::blocking::Client::builder()
-//             .danger_accept_invalid_certs(true)
-//             .build() {
-//             Ok(client) => client,
-//             Err(_) => return Err(Error::ClientError),
-//         };
-//
-//         let resp = match client.get(format!("https://glz-{}-1.{}.a.pvp.net/pregame/v1/matches/{}", auth.region, auth.shard, &match_id))
-//             .bearer_auth(&auth.access_token)
-//             .header("X-Riot-Entitlements-JWT", &auth.token)
-//             .send() {
-//             Ok(resp) => resp,
-//             Err(err) => {
-//                 println!("{:?}", err);
-//                 return Err(Error::RiotError)
-//             }
-//         };
-//
-//         return Ok(resp.json::<PreGame>().unwrap())
-//     }
-//
-//     pub fn get_players(auth: &Loader, prev_match_id: String) -> Result<Vec<LoadedPlayer>, Error> {
-//         if let Ok(match_id) = PreGame::get_match_id(auth) {
-//
-//             if prev_match_id == match_id {
-//                 return Err(Error::NotPreGame)
-//             }
-//
-//             if let Ok(pre_game) = PreGame::get_pre_game(auth, match_id) {
-//                 let player_ids: Vec<String> = pre_game.ally_team.players.iter().map(|player| player.uuid.clone()).collect();
-//
-//                 if let Some(player_names) = name_service::NameService::get_names(auth, player_ids) {
-//                     let mut players = Vec::new();
-//
-//                     for name in player_names {
-//                         players.push(LoadedPlayer {
-//                             uuid: name.uuid,
-//                             name: name.game_name,
-//                             tag: name.tag_line,
-//                             team: TeamType::Ally,
-//
-//                             match_history: Vec::new(),
-//                             name_history: Vec::new(),
-//
-//                             last_played: 0_i64,
-//                             times_played: 0_i64,
-//
-//                             agent_id: String::from("0"),
-//                             incognito: false,
-//                         });
-//                     }
-//
-//                     return Ok(players)
-//                 } else {
-//                     // issue with retrieving names
-//                 }
-//             } else {
-//                 // couldnt get pre game
-//             }
-//         } else {
-//             // couldnt get match id
-//         }
-//
-//         return Err(Error::Unknown)
-//     }
-// }
-//
-//
-//
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+use reqwest::StatusCode;
+use crate::{LoadedPlayer, name_service, TeamType};
+use crate::r#match::{load_histories, PlayerHistory};
+use crate::api::PvpApi;
+use crate::riot_client::RiotError;
+
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct PreGameId {
+    #[serde(rename = "MatchID")]
+    pub match_id: String,
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct PreGame {
+    #[serde(rename = "ID")]
+    pub match_id: String,
+    #[serde(rename = "MapID")]
+    pub map_id: String,
+    #[serde(rename = "Mode")]
+    pub gamemode_id: String,
+    #[serde(rename = "GamePodID")]
+    pub game_pod: String,
+    #[serde(rename = "AllyTeam")]
+    pub ally_team: Option<Team>,
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct Team {
+    #[serde(rename = "Players")]
+    pub players: Vec<Player>
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct Player {
+    #[serde(rename = "Subject")]
+    pub uuid: String,
+    #[serde(rename = "CharacterID")]
+    pub agent_id: String,
+    #[serde(rename = "PlayerIdentity")]
+    pub player_identity: PlayerIdentity
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct PlayerIdentity {
+    #[serde(rename = "Incognito")]
+    pub incognito: bool,
+}
+
+#[derive(Debug)]
+pub enum Error { NotPreGame, Riot(RiotError), NameService(RiotError) }
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotPreGame => write!(f, "not in pre-game"),
+            Error::Riot(err) => write!(f, "{}", err),
+            Error::NameService(err) => write!(f, "could not get player names: {}", err),
+        }
+    }
+}
+
+impl From<RiotError> for Error {
+    fn from(err: RiotError) -> Self {
+        match err {
+            RiotError::Status(StatusCode::NOT_FOUND) => Error::NotPreGame,
+            err => Error::Riot(err),
+        }
+    }
+}
+
+impl PreGame {
+    // Looks up the ally team in the database without recording anything,
+    // the match is only stored once it has loaded into core-game
+    pub async fn get_players(&self, api: &impl PvpApi) -> Result<Vec<LoadedPlayer>, Error> {
+        let Some(ally_team) = &self.ally_team else {
+            return Ok(Vec::new())
+        };
+
+        let player_ids: Vec<String> = ally_team.players.iter().map(|player| player.uuid.clone()).collect();
+        let time_now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+
+        let (player_names, mut histories) = tokio::join!(
+            name_service::NameService::get_names(api, player_ids.clone()),
+            load_histories(player_ids, time_now),
+        );
+
+        let player_names = player_names.map_err(Error::NameService)?;
+
+        let mut players = Vec::new();
+
+        for player in &ally_team.players {
+            let Some(name) = player_names.iter().find(|x| x.uuid == player.uuid) else {
+                continue
+            };
+
+            let history = histories.remove(&name.uuid).unwrap_or_else(|| PlayerHistory::new(time_now));
+
+            players.push(LoadedPlayer {
+                uuid: name.uuid.clone(),
+                name: name.game_name.clone(),
+                tag: name.tag_line.clone(),
+                team: TeamType::Ally,
+
+                match_history: history.match_history,
+                name_history: history.name_history,
+
+                times_played: history.times_played,
+                last_played: history.last_played,
+                note: history.note,
+                flag: history.flag,
+                flag_reason: history.flag_reason,
+                encounters: history.encounters,
+
+                agent_id: player.agent_id.clone(),
+                incognito: player.player_identity.incognito,
+            });
+        }
+
+        Ok(players)
+    }
+}
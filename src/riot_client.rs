@@ -0,0 +1,254 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use reqwest::{Client, Method, Response, StatusCode};
+use reqwest::header::RETRY_AFTER;
+use serde::de::DeserializeOwned;
+use crate::api::PvpApi;
+use crate::loader::Loader;
+use crate::name_service::NameService;
+use crate::pre_game::{PreGame, PreGameId};
+use crate::r#match::{CurrentGameMatch, CurrentGamePlayer};
+use crate::match_details::MatchDetails;
+
+// Base64 of {"platformType": "PC", "platformOS": "Windows", "platformOSVersion": "10.0.19042.1.256.64bit", "platformChipset": "Unknown"}
+const CLIENT_PLATFORM: &str = "ew0KCSJwbGF0Zm9ybVR5cGUiOiAiUEMiLA0KCSJwbGF0Zm9ybU9TIjogIldpbmRvd3MiLA0KCSJwbGF0Zm9ybU9TVmVyc2lvbiI6ICIxMC4wLjE5MDQyLjEuMjU2LjY0Yml0IiwNCgkicGxhdGZvcm1DaGlwc2V0IjogIlVua25vd24iDQp9";
+
+#[derive(Debug)]
+pub enum RiotError {
+    Request(reqwest::Error),
+    Status(StatusCode),
+    Deserialize(reqwest::Error),
+    // Parsed fine but is missing something we rely on
+    Incomplete(&'static str),
+}
+
+impl fmt::Display for RiotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RiotError::Request(err) => write!(f, "request failed: {}", err),
+            RiotError::Status(status) => write!(f, "server returned {}", status),
+            RiotError::Deserialize(err) => write!(f, "unexpected response: {}", err),
+            RiotError::Incomplete(missing) => write!(f, "response is missing {}", missing),
+        }
+    }
+}
+
+impl std::error::Error for RiotError {}
+
+// How often and how long to wait before giving up on a rate limited or failing request
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    // Also the longest Retry-After that is waited on, anything longer is returned as a 429
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    // Doubles with every attempt, capped at max_delay
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay)
+    }
+
+    // How long to wait before sending again, None if the result is final
+    fn delay(&self, attempt: u32, result: &reqwest::Result<Response>) -> Option<Duration> {
+        if attempt >= self.max_retries {
+            return None
+        }
+
+        match result {
+            Ok(res) if res.status() == StatusCode::TOO_MANY_REQUESTS => match retry_after(res) {
+                Some(wait) if wait > self.max_delay => None,
+                Some(wait) => Some(wait),
+                None => Some(self.backoff(attempt)),
+            },
+            Ok(res) if res.status().is_server_error() => Some(self.backoff(attempt)),
+            Ok(_) => None,
+            Err(err) if err.is_timeout() || err.is_connect() => Some(self.backoff(attempt)),
+            Err(_) => None,
+        }
+    }
+}
+
+// Riot sends Retry-After as a number of seconds
+fn retry_after(res: &Response) -> Option<Duration> {
+    let value = res.headers().get(RETRY_AFTER)?.to_str().ok()?;
+
+    value.trim().parse::<u64>().ok().map(Duration::from_secs)
+}
+
+// Authenticated client for the remote PVP endpoints.
+// Cloning is cheap and shares the loader's connection pool.
+#[derive(Debug, Clone, Default)]
+pub struct RiotClient {
+    http: Client,
+    auth: Arc<Loader>,
+    retry: RetryPolicy,
+}
+
+impl RiotClient {
+    pub fn new(auth: Arc<Loader>) -> Self {
+        Self {
+            http: auth.http_client(),
+            auth,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    pub fn glz_url(&self, path: &str) -> String {
+        format!("{}{}", self.auth.glz_url(), path)
+    }
+
+    pub fn pd_url(&self, path: &str) -> String {
+        format!("{}{}", self.auth.pd_url(), path)
+    }
+
+    pub fn shared_url(&self, path: &str) -> String {
+        format!("{}{}", self.auth.shared_url(), path)
+    }
+
+    // Retries 429s, 5xxs and connection failures following the retry policy
+    async fn send<T: DeserializeOwned>(&self, method: Method, url: String, body: Option<String>) -> Result<T, RiotError> {
+        let mut attempt = 0;
+
+        let res = loop {
+            let result = self.auth.send_authorized(|tokens| {
+                let request = self.http.request(method.clone(), &url)
+                    .bearer_auth(&tokens.access_token)
+                    .header("X-Riot-Entitlements-JWT", &tokens.token)
+                    .header("X-Riot-ClientPlatform", CLIENT_PLATFORM)
+                    .header("X-Riot-ClientVersion", &self.auth.client_version);
+
+                match &body {
+                    Some(body) => request.body(body.clone()),
+                    None => request,
+                }
+            }).await;
+
+            match self.retry.delay(attempt, &result) {
+                Some(delay) => {
+                    println!("{} {}: retrying in {:?}", method, url, delay);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                None => break result.map_err(RiotError::Request)?,
+            }
+        };
+
+        println!("{} {}: {}", method, url, res.status());
+
+        if !res.status().is_success() {
+            return Err(RiotError::Status(res.status()))
+        }
+
+        res.json::<T>().await.map_err(RiotError::Deserialize)
+    }
+
+    pub async fn glz_get<T: DeserializeOwned>(&self, path: &str) -> Result<T, RiotError> {
+        self.send(Method::GET, self.glz_url(path), None).await
+    }
+
+    pub async fn pd_get<T: DeserializeOwned>(&self, path: &str) -> Result<T, RiotError> {
+        self.send(Method::GET, self.pd_url(path), None).await
+    }
+
+    pub async fn pd_put<T: DeserializeOwned>(&self, path: &str, body: String) -> Result<T, RiotError> {
+        self.send(Method::PUT, self.pd_url(path), Some(body)).await
+    }
+
+    #[allow(dead_code)] // Nothing reads from the shared endpoints yet
+    pub async fn shared_get<T: DeserializeOwned>(&self, path: &str) -> Result<T, RiotError> {
+        self.send(Method::GET, self.shared_url(path), None).await
+    }
+}
+
+#[async_trait]
+impl PvpApi for RiotClient {
+    fn puuid(&self) -> &str {
+        &self.auth.puuid
+    }
+
+    async fn core_game_player(&self) -> Result<CurrentGamePlayer, RiotError> {
+        self.glz_get(&format!("/core-game/v1/players/{}", self.auth.puuid)).await
+    }
+
+    async fn core_game_match(&self, match_id: &str) -> Result<CurrentGameMatch, RiotError> {
+        self.glz_get(&format!("/core-game/v1/matches/{}", match_id)).await
+    }
+
+    async fn pre_game_player(&self) -> Result<PreGameId, RiotError> {
+        self.glz_get(&format!("/pregame/v1/players/{}", self.auth.puuid)).await
+    }
+
+    async fn pre_game_match(&self, match_id: &str) -> Result<PreGame, RiotError> {
+        self.glz_get(&format!("/pregame/v1/matches/{}", match_id)).await
+    }
+
+    async fn names(&self, puuids: &[String]) -> Result<Vec<NameService>, RiotError> {
+        self.pd_put("/name-service/v2/players", serde_json::to_string(puuids).unwrap()).await
+    }
+
+    async fn match_details(&self, match_id: &str) -> Result<MatchDetails, RiotError> {
+        self.pd_get(&format!("/match-details/v1/matches/{}", match_id)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: u16, retry_after: Option<&str>) -> reqwest::Result<Response> {
+        let mut res = http::Response::builder().status(status);
+
+        if let Some(retry_after) = retry_after {
+            res = res.header(RETRY_AFTER, retry_after);
+        }
+
+        Ok(Response::from(res.body("").unwrap()))
+    }
+
+    #[test]
+    fn backs_off_exponentially() {
+        let policy = RetryPolicy::default();
+
+        assert_eq!(policy.backoff(0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff(10), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn honors_retry_after() {
+        let policy = RetryPolicy::default();
+
+        assert_eq!(policy.delay(0, &response(429, Some("3"))), Some(Duration::from_secs(3)));
+        assert_eq!(policy.delay(0, &response(429, None)), Some(Duration::from_millis(500)));
+        // Longer than we're willing to wait
+        assert_eq!(policy.delay(0, &response(429, Some("120"))), None);
+    }
+
+    #[test]
+    fn retries_only_transient_statuses() {
+        let policy = RetryPolicy::default();
+
+        assert_eq!(policy.delay(1, &response(503, None)), Some(Duration::from_secs(1)));
+        assert_eq!(policy.delay(0, &response(404, None)), None);
+        assert_eq!(policy.delay(0, &response(401, None)), None);
+        assert_eq!(policy.delay(0, &response(200, None)), None);
+        assert_eq!(policy.delay(policy.max_retries, &response(503, None)), None);
+    }
+}
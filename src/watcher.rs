@@ -0,0 +1,108 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+pub const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, PartialEq)]
+pub enum LockfileEvent {
+    Unchanged,
+    // Riot Client restarted and wrote a new port and password
+    Changed,
+    // Riot Client closed
+    Removed,
+}
+
+// Polls the lockfile's mtime and contents, the contents catch rewrites within the same mtime tick
+#[derive(Debug)]
+pub struct LockfileWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    contents: Option<String>,
+    last_polled: Instant,
+}
+
+impl LockfileWatcher {
+    pub fn new(path: PathBuf) -> Self {
+        let (modified, contents) = Self::snapshot(&path);
+
+        Self {
+            path,
+            modified,
+            contents,
+            last_polled: Instant::now(),
+        }
+    }
+
+    fn snapshot(path: &Path) -> (Option<SystemTime>, Option<String>) {
+        let modified = std::fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        let contents = std::fs::read_to_string(path).ok();
+
+        (modified, contents)
+    }
+
+    pub fn poll(&mut self) -> LockfileEvent {
+        if self.last_polled.elapsed() < POLL_INTERVAL {
+            return LockfileEvent::Unchanged
+        }
+
+        self.last_polled = Instant::now();
+
+        if !self.path.is_file() {
+            return LockfileEvent::Removed
+        }
+
+        let (modified, contents) = Self::snapshot(&self.path);
+
+        // The client can hold the file open while rewriting it, try again next poll
+        if contents.is_none() {
+            return LockfileEvent::Unchanged
+        }
+
+        if modified == self.modified && contents == self.contents {
+            return LockfileEvent::Unchanged
+        }
+
+        self.modified = modified;
+        self.contents = contents;
+
+        LockfileEvent::Changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watcher_for(name: &str, contents: &str) -> (PathBuf, LockfileWatcher) {
+        let path = std::env::temp_dir().join(format!("valplus_{}_{}", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+
+        let mut watcher = LockfileWatcher::new(path.clone());
+        watcher.last_polled -= POLL_INTERVAL;
+
+        (path, watcher)
+    }
+
+    #[test]
+    fn detects_restart() {
+        let (path, mut watcher) = watcher_for("restart", "Riot Client:1:443:password:https");
+        assert_eq!(watcher.poll(), LockfileEvent::Unchanged);
+
+        std::fs::write(&path, "Riot Client:2:444:password:https").unwrap();
+        watcher.last_polled -= POLL_INTERVAL;
+        assert_eq!(watcher.poll(), LockfileEvent::Changed);
+
+        watcher.last_polled -= POLL_INTERVAL;
+        assert_eq!(watcher.poll(), LockfileEvent::Unchanged);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn detects_removal() {
+        let (path, mut watcher) = watcher_for("removal", "Riot Client:1:443:password:https");
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(watcher.poll(), LockfileEvent::Removed);
+    }
+}